@@ -0,0 +1 @@
+ALTER TABLE sso_nonce ADD COLUMN correlation_id TEXT DEFAULT NULL;
@@ -0,0 +1,3 @@
+ALTER TABLE devices ADD COLUMN encrypted_user_key TEXT DEFAULT NULL;
+ALTER TABLE devices ADD COLUMN encrypted_public_key TEXT DEFAULT NULL;
+ALTER TABLE devices ADD COLUMN encrypted_private_key TEXT DEFAULT NULL;
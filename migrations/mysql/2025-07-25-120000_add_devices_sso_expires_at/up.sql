@@ -0,0 +1 @@
+ALTER TABLE devices ADD COLUMN sso_expires_at DATETIME DEFAULT NULL;
@@ -0,0 +1 @@
+ALTER TABLE sso_nonce ADD COLUMN return_path TEXT DEFAULT NULL;
@@ -0,0 +1,2 @@
+ALTER TABLE sso_nonce ADD COLUMN device_id TEXT DEFAULT NULL;
+ALTER TABLE sso_nonce ADD COLUMN client_type TEXT DEFAULT NULL;
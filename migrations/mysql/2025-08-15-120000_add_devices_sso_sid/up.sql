@@ -0,0 +1 @@
+ALTER TABLE devices ADD COLUMN sso_sid TEXT DEFAULT NULL;
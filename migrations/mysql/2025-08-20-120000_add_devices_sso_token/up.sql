@@ -0,0 +1 @@
+ALTER TABLE devices ADD COLUMN sso_token TEXT DEFAULT NULL;
@@ -0,0 +1 @@
+ALTER TABLE users_organizations ADD COLUMN sso_stale BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1 @@
+ALTER TABLE users_organizations ADD COLUMN scim_managed BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1 @@
+ALTER TABLE users_organizations ADD COLUMN sso_seen_at TIMESTAMP DEFAULT NULL;
@@ -0,0 +1 @@
+ALTER TABLE devices ADD COLUMN sso_login BOOLEAN NOT NULL DEFAULT FALSE;
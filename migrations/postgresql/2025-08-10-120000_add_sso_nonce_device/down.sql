@@ -0,0 +1,2 @@
+ALTER TABLE sso_nonce DROP COLUMN device_id;
+ALTER TABLE sso_nonce DROP COLUMN client_type;
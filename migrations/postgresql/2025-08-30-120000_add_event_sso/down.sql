@@ -0,0 +1,3 @@
+ALTER TABLE event DROP COLUMN sso_provider;
+ALTER TABLE event DROP COLUMN sso_subject;
+ALTER TABLE event DROP COLUMN sso_detail;
@@ -0,0 +1 @@
+ALTER TABLE sso_nonce ADD COLUMN authenticated_user TEXT DEFAULT NULL;
@@ -0,0 +1 @@
+ALTER TABLE sso_nonce DROP COLUMN correlation_id;
@@ -0,0 +1 @@
+ALTER TABLE users DROP COLUMN uses_key_connector;
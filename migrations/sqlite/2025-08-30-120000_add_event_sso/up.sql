@@ -0,0 +1,3 @@
+ALTER TABLE event ADD COLUMN sso_provider TEXT DEFAULT NULL;
+ALTER TABLE event ADD COLUMN sso_subject TEXT DEFAULT NULL;
+ALTER TABLE event ADD COLUMN sso_detail TEXT DEFAULT NULL;
@@ -1,3 +1,4 @@
+use chrono::Utc;
 use once_cell::sync::Lazy;
 use reqwest::Method;
 use serde::de::DeserializeOwned;
@@ -7,7 +8,7 @@ use std::env;
 use rocket::serde::json::Json;
 use rocket::{
     form::Form,
-    http::{Cookie, CookieJar, MediaType, SameSite, Status},
+    http::{ContentType, Cookie, CookieJar, MediaType, SameSite, Status},
     request::{FromRequest, Outcome, Request},
     response::{content::RawHtml as Html, Redirect},
     Catcher, Route,
@@ -19,14 +20,15 @@ use crate::{
         unregister_push_device, ApiResult, EmptyResult, JsonResult, Notify,
     },
     auth::{decode_admin, encode_jwt, generate_admin_claims, ClientIp},
-    business::organization_logic::admin_check,
+    business::{organization_logic::admin_check, user_logic},
     config::ConfigBuilder,
     db::{backup_database, get_sql_server_version, models::*, DbConn, DbConnType},
     error::{Error, MapResult},
     http_client::make_http_request,
-    mail,
+    mail, sso,
+    sso::OIDCState,
     util::{
-        container_base_image, format_naive_datetime_local, get_display_size, get_web_vault_version,
+        container_base_image, format_date, format_naive_datetime_local, get_display_size, get_web_vault_version,
         is_running_in_container, NumberOrString,
     },
     CONFIG, VERSION,
@@ -38,6 +40,10 @@ pub fn routes() -> Vec<Route> {
         && !CONFIG.is_admin_token_set()
         && !(CONFIG.sso_enabled() && CONFIG.sso_roles_enabled())
     {
+        // The metrics can still be scraped with `METRICS_TOKEN`
+        if CONFIG.metrics_token().is_some() {
+            return routes![admin_disabled, sso_metrics];
+        }
         return routes![admin_disabled];
     }
 
@@ -69,6 +75,10 @@ pub fn routes() -> Vec<Route> {
         get_diagnostics_config,
         resend_user_invite,
         get_diagnostics_http,
+        sso_metrics,
+        get_sso_nonces,
+        delete_sso_nonce,
+        delete_sso_nonces,
     ]
 }
 
@@ -361,6 +371,7 @@ async fn get_users_json(_token: AdminToken, mut conn: DbConn) -> Json<Value> {
 #[get("/users/overview")]
 async fn users_overview(_token: AdminToken, mut conn: DbConn) -> ApiResult<Html<String>> {
     let users = User::get_all(&mut conn).await;
+    let sso_sessions = Device::count_sso_sessions_by_user(&mut conn).await;
     let mut users_json = Vec::with_capacity(users.len());
     for (u, sso_u) in users {
         let mut usr = u.to_json(&mut conn).await;
@@ -375,6 +386,7 @@ async fn users_overview(_token: AdminToken, mut conn: DbConn) -> ApiResult<Html<
         };
 
         usr["sso_identifier"] = json!(sso_u.map(|u| u.identifier.to_string()).unwrap_or(String::new()));
+        usr["sso_sessions"] = json!(sso_sessions.get(&u.uuid).copied().unwrap_or(0));
 
         users_json.push(usr);
     }
@@ -410,6 +422,7 @@ async fn delete_user(user_id: UserId, token: AdminToken, mut conn: DbConn) -> Em
 
     // Get the membership records before deleting the actual user
     let memberships = Membership::find_any_state_by_user(&user_id, &mut conn).await;
+    sso::revoke_user_tokens(&user.uuid, &mut conn).await;
     let res = user.delete(&mut conn).await;
 
     for membership in memberships {
@@ -429,24 +442,14 @@ async fn delete_user(user_id: UserId, token: AdminToken, mut conn: DbConn) -> Em
 }
 
 #[delete("/users/<user_id>/sso", format = "application/json")]
-async fn delete_sso_user(user_id: UserId, token: AdminToken, mut conn: DbConn) -> EmptyResult {
-    let memberships = Membership::find_any_state_by_user(&user_id, &mut conn).await;
-    let res = SsoUser::delete(&user_id, &mut conn).await;
+async fn delete_sso_user(user_id: UserId, token: AdminToken, mut conn: DbConn, nt: Notify<'_>) -> EmptyResult {
+    let mut user = get_user_or_404(&user_id, &mut conn).await?;
 
-    for membership in memberships {
-        log_event(
-            EventType::OrganizationUserUnlinkedSso as i32,
-            &membership.uuid,
-            &membership.org_uuid,
-            &ACTING_ADMIN_USER.into(),
-            14, // Use UnknownBrowser type
-            &token.ip.ip,
-            &mut conn,
-        )
-        .await;
-    }
+    // Use UnknownBrowser type
+    user_logic::unlink_sso(&mut user, &ACTING_ADMIN_USER.into(), 14, &token.ip.ip, &mut conn).await?;
 
-    res
+    nt.send_logout(&user, None, &mut conn).await;
+    Ok(())
 }
 
 #[post("/users/<user_id>/deauth", format = "application/json")]
@@ -464,6 +467,7 @@ async fn deauth_user(user_id: UserId, _token: AdminToken, mut conn: DbConn, nt:
         }
     }
 
+    sso::revoke_user_tokens(&user.uuid, &mut conn).await;
     Device::delete_all_by_user(&user.uuid, &mut conn).await?;
     user.reset_security_stamp();
 
@@ -473,15 +477,7 @@ async fn deauth_user(user_id: UserId, _token: AdminToken, mut conn: DbConn, nt:
 #[post("/users/<user_id>/disable", format = "application/json")]
 async fn disable_user(user_id: UserId, _token: AdminToken, mut conn: DbConn, nt: Notify<'_>) -> EmptyResult {
     let mut user = get_user_or_404(&user_id, &mut conn).await?;
-    Device::delete_all_by_user(&user.uuid, &mut conn).await?;
-    user.reset_security_stamp();
-    user.enabled = false;
-
-    let save_result = user.save(&mut conn).await;
-
-    nt.send_logout(&user, None, &mut conn).await;
-
-    save_result
+    user_logic::disable_user(&mut user, &nt, &mut conn).await
 }
 
 #[post("/users/<user_id>/enable", format = "application/json")]
@@ -505,7 +501,7 @@ async fn remove_2fa(user_id: UserId, token: AdminToken, mut conn: DbConn) -> Emp
 async fn resend_user_invite(user_id: UserId, _token: AdminToken, mut conn: DbConn) -> EmptyResult {
     if let Some(user) = User::find_by_uuid(&user_id, &mut conn).await {
         //TODO: replace this with user.status check when it will be available (PR#3397)
-        if !user.password_hash.is_empty() {
+        if user.is_registered() {
             err_code!("User already accepted invitation", Status::BadRequest.code);
         }
 
@@ -690,6 +686,55 @@ async fn get_ntp_time(has_http_access: bool) -> String {
     String::from("Unable to fetch NTP time.")
 }
 
+#[get("/metrics")]
+async fn sso_metrics(_token: MetricsToken, mut conn: DbConn) -> (ContentType, String) {
+    let nonces = SsoNonce::count_active(&mut conn).await;
+    let metrics = crate::metrics::render_sso(sso::pending_authentications(), nonces.max(0) as u64);
+    (ContentType::new("text", "plain").with_params(("version", "0.0.4")), metrics)
+}
+
+// Pending SSO flows, the nonce and PKCE verifier are not returned
+#[get("/sso/nonces")]
+async fn get_sso_nonces(_token: AdminToken, mut conn: DbConn) -> Json<Value> {
+    let now = Utc::now().naive_utc();
+    let nonces: Vec<Value> = SsoNonce::find_all(&mut conn)
+        .await
+        .into_iter()
+        .map(|nonce| {
+            json!({
+                "state": nonce.state,
+                "createdAt": format_date(&nonce.created_at),
+                "ageSeconds": (now - nonce.created_at).num_seconds(),
+                "expired": nonce.is_expired(),
+                "redirectUri": nonce.redirect_uri,
+                "authenticated": nonce.authenticated_user.is_some(),
+                "correlationId": nonce.correlation_id,
+                "deviceId": nonce.device_id,
+                "clientType": nonce.client_type,
+            })
+        })
+        .collect();
+
+    Json(json!({
+        "data": nonces,
+        "pendingAuthentications": sso::pending_authentications(),
+    }))
+}
+
+#[delete("/sso/nonces/<state>")]
+async fn delete_sso_nonce(state: String, _token: AdminToken, mut conn: DbConn) -> EmptyResult {
+    let state = OIDCState::from(state);
+    info!("Admin aborted the SSO flow {state}");
+    sso::abort_flow(&state, &mut conn).await;
+    Ok(())
+}
+
+#[delete("/sso/nonces")]
+async fn delete_sso_nonces(_token: AdminToken, mut conn: DbConn) -> EmptyResult {
+    info!("Admin aborted all the pending SSO flows");
+    sso::abort_all_flows(&mut conn).await
+}
+
 #[get("/diagnostics")]
 async fn diagnostics(_token: AdminToken, ip_header: IpHeader, mut conn: DbConn) -> ApiResult<Html<String>> {
     use chrono::prelude::*;
@@ -745,6 +790,9 @@ async fn diagnostics(_token: AdminToken, ip_header: IpHeader, mut conn: DbConn)
         "ip_header_config": &CONFIG.ip_header(),
         "uses_proxy": uses_proxy,
         "enable_websocket": &CONFIG.enable_websocket(),
+        "sso_enabled": CONFIG.sso_enabled(),
+        "sso_nonces": SsoNonce::count_active(&mut conn).await,
+        "sso_provider": if CONFIG.sso_enabled() { Some(sso::provider_health().await) } else { None },
         "db_type": *DB_TYPE,
         "db_version": get_sql_server_version(&mut conn).await,
         "admin_url": format!("{}/diagnostics", admin_url()),
@@ -850,3 +898,38 @@ impl<'r> FromRequest<'r> for AdminToken {
         }
     }
 }
+
+// `METRICS_TOKEN` as a bearer token for a Prometheus scraper, otherwise an admin session
+pub struct MetricsToken;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for MetricsToken {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(bearer) = request.headers().get_one("Authorization").and_then(|a| a.strip_prefix("Bearer ")) else {
+            return AdminToken::from_request(request).await.map(|_| Self);
+        };
+
+        let ip = match ClientIp::from_request(request).await {
+            Outcome::Success(ip) => ip,
+            _ => err_handler!("Error getting Client IP"),
+        };
+
+        let Some(metrics_token) = CONFIG.metrics_token() else {
+            err_handler!("The metrics token is disabled")
+        };
+
+        // The failures share the SSO failure limit, a blocked IP is refused before the token is checked
+        if crate::ratelimit::check_sso_failures(&ip.ip).is_err() {
+            return Outcome::Error((Status::TooManyRequests, "Too many failed metrics requests"));
+        }
+
+        if !crate::crypto::ct_eq(metrics_token.trim(), bearer.trim()) {
+            crate::ratelimit::sso_failure(&ip.ip);
+            err_handler!("Invalid metrics token", format!("IP: {}", ip.ip));
+        }
+
+        Outcome::Success(Self)
+    }
+}
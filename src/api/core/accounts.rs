@@ -12,6 +12,7 @@ use crate::{
         JsonResult, Notify, PasswordOrOtpData, UpdateType,
     },
     auth::{decode_delete, decode_invite, decode_verify_email, ClientHeaders, Headers},
+    business::user_logic,
     crypto,
     db::{models::*, DbConn},
     mail,
@@ -35,6 +36,8 @@ pub fn routes() -> Vec<rocket::Route> {
         post_keys,
         post_password,
         post_set_password,
+        post_set_key_connector_key,
+        post_convert_to_key_connector,
         post_kdf,
         post_rotatekey,
         post_sstamp,
@@ -46,6 +49,7 @@ pub fn routes() -> Vec<rocket::Route> {
         post_delete_recover_token,
         post_delete_account,
         delete_account,
+        delete_sso_link,
         revision_date,
         password_hint,
         prelogin,
@@ -55,11 +59,15 @@ pub fn routes() -> Vec<rocket::Route> {
         get_known_device,
         get_all_devices,
         get_device,
+        put_device_keys,
+        post_device_keys,
+        post_untrust_devices,
         post_device_token,
         put_device_token,
         put_clear_device_token,
         post_clear_device_token,
         post_auth_request,
+        post_admin_auth_request,
         get_auth_request,
         put_auth_request,
         get_auth_request_response,
@@ -112,6 +120,19 @@ pub struct SetPasswordData {
     org_identifier: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetKeyConnectorKeyData {
+    kdf: i32,
+    kdf_iterations: i32,
+    kdf_memory: Option<i32>,
+    kdf_parallelism: Option<i32>,
+    key: String,
+    keys: KeysData,
+    #[allow(dead_code)]
+    org_identifier: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct KeysData {
@@ -238,7 +259,7 @@ pub async fn _register(data: Json<RegisterData>, email_verification: bool, mut c
 
     let mut user = match User::find_by_mail(&email, &mut conn).await {
         Some(user) => {
-            if !user.password_hash.is_empty() {
+            if user.is_registered() {
                 err!("Registration not allowed or user already exists")
             }
 
@@ -411,6 +432,83 @@ async fn post_set_password(data: Json<SetPasswordData>, headers: Headers, mut co
     })))
 }
 
+// Called by a new SSO user after pushing its generated master key to the Key Connector
+#[post("/accounts/set-key-connector-key", data = "<data>")]
+async fn post_set_key_connector_key(
+    data: Json<SetKeyConnectorKeyData>,
+    headers: Headers,
+    mut conn: DbConn,
+) -> EmptyResult {
+    let mut user = headers.user;
+    set_key_connector_key(&mut user, data.into_inner(), &mut conn).await
+}
+
+async fn set_key_connector_key(user: &mut User, data: SetKeyConnectorKeyData, conn: &mut DbConn) -> EmptyResult {
+    if user.private_key.is_some() || user.is_registered() {
+        err!("Account already initialized cannot set the Key Connector key")
+    }
+
+    if crate::sso::user_key_connector_url(&user.uuid, conn).await.is_none() {
+        err!("No Key Connector is configured for this account")
+    }
+
+    user.client_kdf_type = data.kdf;
+    user.client_kdf_iter = data.kdf_iterations;
+    user.client_kdf_memory = data.kdf_memory;
+    user.client_kdf_parallelism = data.kdf_parallelism;
+
+    user.akey = data.key;
+    user.private_key = Some(data.keys.encrypted_private_key);
+    user.public_key = Some(data.keys.public_key);
+    user.uses_key_connector = true;
+
+    if CONFIG.mail_enabled() {
+        mail::send_welcome(&user.email.to_lowercase()).await?;
+    } else {
+        // Since the user now has a key we can confirm invitations.
+        Membership::accept_user_invitations(&user.uuid, conn).await?;
+    }
+
+    user.save(conn).await
+}
+
+// Called by an existing user after pushing its master key to the Key Connector, the master password is removed
+#[post("/accounts/convert-to-key-connector")]
+async fn post_convert_to_key_connector(headers: Headers, mut conn: DbConn) -> EmptyResult {
+    let mut user = headers.user;
+    convert_to_key_connector(&mut user, &mut conn).await?;
+
+    log_user_event(
+        EventType::UserMigratedKeyToKeyConnector as i32,
+        &user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &mut conn,
+    )
+    .await;
+
+    Ok(())
+}
+
+async fn convert_to_key_connector(user: &mut User, conn: &mut DbConn) -> EmptyResult {
+    if user.uses_key_connector {
+        err!("Account already uses a Key Connector")
+    }
+
+    if user.password_hash.is_empty() {
+        err!("Account has no master password to convert")
+    }
+
+    if crate::sso::user_key_connector_url(&user.uuid, conn).await.is_none() {
+        err!("No Key Connector is configured for this account")
+    }
+
+    user.password_hash = Vec::new();
+    user.password_hint = None;
+    user.uses_key_connector = true;
+    user.save(conn).await
+}
+
 #[get("/accounts/profile")]
 async fn profile(headers: Headers, mut conn: DbConn) -> Json<Value> {
     Json(headers.user.to_json(&mut conn).await)
@@ -812,6 +910,9 @@ async fn post_rotatekey(data: Json<KeyData>, headers: Headers, mut conn: DbConn,
     user.private_key = Some(data.private_key);
     user.reset_security_stamp();
 
+    // The trusted devices hold the previous user key, they will need to be approved again
+    Device::delete_trust_by_user(&user.uuid, &mut conn).await?;
+
     let save_result = user.save(&mut conn).await;
 
     // Prevent logging out the client where the user requested this endpoint from.
@@ -829,6 +930,7 @@ async fn post_sstamp(data: Json<PasswordOrOtpData>, headers: Headers, mut conn:
 
     data.validate(&user, true, &mut conn).await?;
 
+    crate::sso::revoke_user_tokens(&user.uuid, &mut conn).await;
     Device::delete_all_by_user(&user.uuid, &mut conn).await?;
     user.reset_security_stamp();
     let save_result = user.save(&mut conn).await;
@@ -1045,6 +1147,7 @@ async fn post_delete_recover_token(data: Json<DeleteRecoverTokenData>, mut conn:
     if claims.sub != *user.uuid {
         err!("Invalid claim");
     }
+    crate::sso::revoke_user_tokens(&user.uuid, &mut conn).await;
     user.delete(&mut conn).await
 }
 
@@ -1060,9 +1163,39 @@ async fn delete_account(data: Json<PasswordOrOtpData>, headers: Headers, mut con
 
     data.validate(&user, true, &mut conn).await?;
 
+    crate::sso::revoke_user_tokens(&user.uuid, &mut conn).await;
     user.delete(&mut conn).await
 }
 
+// Detach the SSO identity, the user can then log in using its master password.
+#[delete("/accounts/sso", data = "<data>")]
+async fn delete_sso_link(
+    data: Json<PasswordOrOtpData>,
+    headers: Headers,
+    mut conn: DbConn,
+    nt: Notify<'_>,
+) -> EmptyResult {
+    let data: PasswordOrOtpData = data.into_inner();
+    let mut user = headers.user;
+
+    if CONFIG.sso_enabled() && CONFIG.sso_only() {
+        err!("SSO sign-in is required, ask your administrator to remove the SSO association")
+    }
+
+    data.validate(&user, true, &mut conn).await?;
+
+    if SsoUser::find_by_mail(&user.email, &conn).await.and_then(|(_, sso_user)| sso_user).is_none() {
+        err!("Your account is not associated with an SSO identity")
+    }
+
+    let user_id = user.uuid.clone();
+    user_logic::unlink_sso(&mut user, &user_id, headers.device.atype, &headers.ip.ip, &mut conn).await?;
+    info!("User {} removed its SSO association", user.uuid);
+
+    nt.send_logout(&user, None, &mut conn).await;
+    Ok(())
+}
+
 #[get("/accounts/revision-date")]
 fn revision_date(headers: Headers) -> JsonResult {
     let revision_date = headers.user.updated_at.and_utc().timestamp_millis();
@@ -1274,6 +1407,63 @@ async fn get_device(device_id: DeviceId, headers: Headers, mut conn: DbConn) ->
     Ok(Json(device.to_json()))
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DeviceKeysData {
+    encrypted_user_key: String,
+    encrypted_public_key: String,
+    encrypted_private_key: String,
+}
+
+// Trust the device (trusted device encryption), its keys are returned in the next SSO logins
+#[put("/devices/<device_id>/keys", data = "<data>")]
+async fn put_device_keys(
+    device_id: DeviceId,
+    data: Json<DeviceKeysData>,
+    headers: Headers,
+    mut conn: DbConn,
+) -> JsonResult {
+    let data: DeviceKeysData = data.into_inner();
+
+    let Some(mut device) = Device::find_by_uuid_and_user(&device_id, &headers.user.uuid, &mut conn).await else {
+        err!("No device found");
+    };
+
+    device.encrypted_user_key = Some(data.encrypted_user_key);
+    device.encrypted_public_key = Some(data.encrypted_public_key);
+    device.encrypted_private_key = Some(data.encrypted_private_key);
+    device.save(&mut conn).await?;
+
+    Ok(Json(device.to_json()))
+}
+
+#[post("/devices/<device_id>/keys", data = "<data>")]
+async fn post_device_keys(
+    device_id: DeviceId,
+    data: Json<DeviceKeysData>,
+    headers: Headers,
+    conn: DbConn,
+) -> JsonResult {
+    put_device_keys(device_id, data, headers, conn).await
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UntrustDevicesData {
+    devices: Vec<DeviceId>,
+}
+
+#[post("/devices/untrust", data = "<data>")]
+async fn post_untrust_devices(data: Json<UntrustDevicesData>, headers: Headers, mut conn: DbConn) -> EmptyResult {
+    for device_id in data.into_inner().devices {
+        if let Some(mut device) = Device::find_by_uuid_and_user(&device_id, &headers.user.uuid, &mut conn).await {
+            device.delete_trust();
+            device.save(&mut conn).await?;
+        }
+    }
+    Ok(())
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct PushToken {
@@ -1412,6 +1602,61 @@ async fn post_auth_request(
     })))
 }
 
+// Trusted device encryption, ask the admins of an organization the user is enrolled in the account recovery to approve the device.
+// The client only tracks one request, when the user is enrolled in multiple organizations the first one (by id) is used.
+#[post("/auth-requests/admin-request", data = "<data>")]
+async fn post_admin_auth_request(data: Json<AuthRequestRequest>, headers: Headers, mut conn: DbConn) -> JsonResult {
+    let data = data.into_inner();
+    let user = headers.user;
+
+    if headers.device.uuid != data.device_identifier || !user.email.eq_ignore_ascii_case(&data.email) {
+        err!("AuthRequest doesn't exist", "Device or user verification failed")
+    }
+
+    let Some(membership) = Membership::find_confirmed_by_user(&user.uuid, &mut conn)
+        .await
+        .into_iter()
+        .filter(|m| m.reset_password_key.is_some() && crate::sso::trusted_device_encryption(&m.org_uuid))
+        .min_by_key(|m| m.org_uuid.to_string())
+    else {
+        err!("No organization can approve this device, you need to be enrolled in the account recovery")
+    };
+
+    let mut auth_request = AuthRequest::new(
+        user.uuid.clone(),
+        data.device_identifier,
+        headers.device.atype,
+        headers.ip.ip.to_string(),
+        data.access_code,
+        data.public_key,
+    );
+    auth_request.organization_uuid = Some(membership.org_uuid);
+    auth_request.save(&mut conn).await?;
+
+    log_user_event(
+        EventType::UserRequestedDeviceApproval as i32,
+        &user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &mut conn,
+    )
+    .await;
+
+    Ok(Json(json!({
+        "id": auth_request.uuid,
+        "publicKey": auth_request.public_key,
+        "requestDeviceType": DeviceType::from_i32(auth_request.device_type).to_string(),
+        "requestIpAddress": auth_request.request_ip,
+        "key": null,
+        "masterPasswordHash": null,
+        "creationDate": format_date(&auth_request.creation_date),
+        "responseDate": null,
+        "requestApproved": false,
+        "origin": CONFIG.domain_origin(),
+        "object": "auth-request"
+    })))
+}
+
 #[get("/auth-requests/<auth_request_id>")]
 async fn get_auth_request(auth_request_id: AuthRequestId, headers: Headers, mut conn: DbConn) -> JsonResult {
     let Some(auth_request) = AuthRequest::find_by_uuid_and_user(&auth_request_id, &headers.user.uuid, &mut conn).await
@@ -1561,7 +1806,7 @@ async fn get_auth_requests(headers: Headers, mut conn: DbConn) -> JsonResult {
     Ok(Json(json!({
         "data": auth_requests
             .iter()
-            .filter(|request| request.approved.is_none())
+            .filter(|request| request.approved.is_none() && !request.is_admin_request())
             .map(|request| {
             let response_date_utc = request.response_date.map(|response_date| format_date(&response_date));
 
@@ -1592,3 +1837,79 @@ pub async fn purge_auth_requests(pool: DbPool) {
         error!("Failed to get DB connection while purging trashed ciphers")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(sqlite)]
+    use crate::{config::tests::TEST_KEY_CONNECTOR_ORG_ID, db::test_conn};
+
+    // User accepted in the organization using the test Key Connector
+    #[cfg(sqlite)]
+    async fn key_connector_member(email: &str, conn: &mut DbConn) -> User {
+        let mut org = Organization::new("key connector".to_string(), "billing@example.com".to_string(), None, None);
+        org.uuid = OrganizationId::from(TEST_KEY_CONNECTOR_ORG_ID.to_string());
+        org.save(conn).await.unwrap();
+
+        let mut user = User::new(email.to_string(), None);
+        user.save(conn).await.unwrap();
+        let mut member = Membership::new(user.uuid.clone(), org.uuid, None);
+        member.status = MembershipStatus::Accepted as i32;
+        member.save(conn).await.unwrap();
+        user
+    }
+
+    #[cfg(sqlite)]
+    #[rocket::async_test]
+    async fn test_set_key_connector_key() {
+        let mut conn = test_conn().await;
+        let data = || -> SetKeyConnectorKeyData {
+            serde_json::from_value(json!({
+                "kdf": 0,
+                "kdfIterations": 600_000,
+                "key": "user-key",
+                "keys": { "encryptedPrivateKey": "private-key", "publicKey": "public-key" },
+                "orgIdentifier": "key connector",
+            }))
+            .unwrap()
+        };
+
+        let mut user = key_connector_member("kc-new@example.com", &mut conn).await;
+        set_key_connector_key(&mut user, data(), &mut conn).await.unwrap();
+        let user = User::find_by_uuid(&user.uuid, &mut conn).await.unwrap();
+        assert!(user.uses_key_connector && user.is_registered());
+        assert_eq!(user.akey, "user-key");
+        assert_eq!(user.private_key.as_deref(), Some("private-key"));
+
+        // Only once, and only for the members of an organization using a Key Connector
+        let mut user = user;
+        let err = set_key_connector_key(&mut user, data(), &mut conn).await.unwrap_err();
+        assert!(err.message().contains("already initialized"));
+
+        let mut other = User::new("kc-none@example.com".to_string(), None);
+        other.save(&mut conn).await.unwrap();
+        let err = set_key_connector_key(&mut other, data(), &mut conn).await.unwrap_err();
+        assert!(err.message().contains("No Key Connector"));
+    }
+
+    #[cfg(sqlite)]
+    #[rocket::async_test]
+    async fn test_convert_to_key_connector() {
+        let mut conn = test_conn().await;
+
+        let mut user = key_connector_member("kc-convert@example.com", &mut conn).await;
+        let err = convert_to_key_connector(&mut user, &mut conn).await.unwrap_err();
+        assert!(err.message().contains("no master password"));
+
+        user.set_password("master-password-hash", None, true, None);
+        user.password_hint = Some("hint".to_string());
+        user.save(&mut conn).await.unwrap();
+        convert_to_key_connector(&mut user, &mut conn).await.unwrap();
+        let mut user = User::find_by_uuid(&user.uuid, &mut conn).await.unwrap();
+        assert!(user.password_hash.is_empty() && user.password_hint.is_none());
+        assert!(user.uses_key_connector && user.is_registered());
+
+        let err = convert_to_key_connector(&mut user, &mut conn).await.unwrap_err();
+        assert!(err.message().contains("already uses"));
+    }
+}
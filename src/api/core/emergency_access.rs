@@ -243,7 +243,7 @@ async fn send_invite(data: Json<EmergencyAccessInviteData>, headers: Headers, mu
             user.save(&mut conn).await?;
             (user, true)
         }
-        Some(user) if user.password_hash.is_empty() => (user, true),
+        Some(user) if !user.is_registered() => (user, true),
         Some(user) => (user, false),
     };
 
@@ -313,7 +313,7 @@ async fn resend_invite(emer_id: EmergencyAccessId, headers: Headers, mut conn: D
             &grantor_user.email,
         )
         .await?;
-    } else if !grantee_user.password_hash.is_empty() {
+    } else if grantee_user.is_registered() {
         // accept the invitation for existing user
         emergency_access.accept_invite(&grantee_user.uuid, &email, &mut conn).await?;
     } else if CONFIG.invitations_allowed() && Invitation::find_by_mail(&email, &mut conn).await.is_none() {
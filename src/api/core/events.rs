@@ -187,6 +187,7 @@ async fn post_events_collect(data: Json<Vec<EventCollection>>, headers: Headers,
                     headers.device.atype,
                     Some(event_date),
                     &headers.ip.ip,
+                    None,
                     &mut conn,
                 )
                 .await;
@@ -234,7 +235,29 @@ pub async fn log_user_event(event_type: i32, user_id: &UserId, device_type: i32,
     if !CONFIG.org_events_enabled() {
         return;
     }
-    _log_user_event(event_type, user_id, device_type, None, ip, conn).await;
+    _log_user_event(event_type, user_id, device_type, None, ip, None, conn).await;
+}
+
+// Provider identity of a SSO login, `detail` is the account kind on success or the category of the refusal
+pub struct SsoEventDetails {
+    pub provider: String,
+    pub subject: String,
+    pub detail: &'static str,
+}
+
+// Login events, with the provider identity for the SSO logins
+pub async fn log_login_event(
+    event_type: i32,
+    user_id: &UserId,
+    device_type: i32,
+    ip: &IpAddr,
+    sso: Option<&SsoEventDetails>,
+    conn: &mut DbConn,
+) {
+    if !CONFIG.org_events_enabled() {
+        return;
+    }
+    _log_user_event(event_type, user_id, device_type, None, ip, sso, conn).await;
 }
 
 async fn _log_user_event(
@@ -243,6 +266,7 @@ async fn _log_user_event(
     device_type: i32,
     event_date: Option<NaiveDateTime>,
     ip: &IpAddr,
+    sso: Option<&SsoEventDetails>,
     conn: &mut DbConn,
 ) {
     let memberships = Membership::find_by_user(user_id, conn).await;
@@ -254,6 +278,7 @@ async fn _log_user_event(
     event.act_user_uuid = Some(user_id.clone());
     event.device_type = Some(device_type);
     event.ip_address = Some(ip.to_string());
+    set_sso_details(&mut event, sso);
     events.push(event);
 
     // For each org a user is a member of store these events per org
@@ -265,12 +290,21 @@ async fn _log_user_event(
         event.act_user_uuid = Some(user_id.clone());
         event.device_type = Some(device_type);
         event.ip_address = Some(ip.to_string());
+        set_sso_details(&mut event, sso);
         events.push(event);
     }
 
     Event::save_user_event(events, conn).await.unwrap_or(());
 }
 
+fn set_sso_details(event: &mut Event, sso: Option<&SsoEventDetails>) {
+    if let Some(sso) = sso {
+        event.sso_provider = Some(sso.provider.clone());
+        event.sso_subject = Some(sso.subject.clone());
+        event.sso_detail = Some(sso.detail.to_string());
+    }
+}
+
 pub async fn log_event(
     event_type: i32,
     source_uuid: &str,
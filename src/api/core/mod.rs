@@ -11,7 +11,7 @@ pub mod two_factor;
 pub use accounts::purge_auth_requests;
 pub use ciphers::{purge_trashed_ciphers, CipherData, CipherSyncData, CipherSyncType};
 pub use emergency_access::{emergency_notification_reminder_job, emergency_request_timeout_job};
-pub use events::{event_cleanup_job, log_event, log_user_event};
+pub use events::{event_cleanup_job, log_event, log_login_event, log_user_event, SsoEventDetails};
 use reqwest::Method;
 pub use sends::purge_sends;
 
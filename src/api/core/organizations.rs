@@ -8,7 +8,7 @@ use crate::api::admin::FAKE_ADMIN_UUID;
 use crate::{
     api::{
         core::{accept_org_invite, log_event, two_factor, CipherSyncData, CipherSyncType},
-        EmptyResult, JsonResult, Notify, PasswordOrOtpData, UpdateType,
+        AnonymousNotify, EmptyResult, JsonResult, Notify, PasswordOrOtpData, UpdateType,
     },
     auth::{decode_invite, AdminHeaders, Headers, ManagerHeaders, ManagerHeadersLoose, OrgMemberHeaders, OwnerHeaders},
     business::organization_logic,
@@ -107,6 +107,10 @@ pub fn routes() -> Vec<Route> {
         rotate_api_key,
         get_billing_metadata,
         get_auto_enroll_status,
+        get_org_auth_requests,
+        post_org_auth_request,
+        post_org_auth_requests,
+        post_deny_org_auth_requests,
     ]
 }
 
@@ -1243,7 +1247,7 @@ async fn _reinvite_member(
         err!("User not found.")
     };
 
-    if !CONFIG.invitations_allowed() && user.password_hash.is_empty() {
+    if !CONFIG.invitations_allowed() && !user.is_registered() {
         err!("Invitations are not allowed.")
     }
 
@@ -1254,7 +1258,7 @@ async fn _reinvite_member(
 
     if CONFIG.mail_enabled() {
         mail::send_invite(&user, org_id.clone(), member.uuid, &org_name, Some(invited_by_email.to_string())).await?;
-    } else if user.password_hash.is_empty() {
+    } else if !user.is_registered() {
         let invitation = Invitation::new(&user.email);
         invitation.save(conn).await?;
     } else {
@@ -2065,6 +2069,8 @@ async fn put_policy(
         }
     }
 
+    check_require_sso_policy(&org_id, pol_type_enum, data.enabled, &mut conn).await?;
+
     // When enabling the TwoFactorAuthentication policy, revoke all members that do not have 2FA
     if pol_type_enum == OrgPolicyType::TwoFactorAuthentication && data.enabled {
         two_factor::enforce_2fa_policy_for_org(
@@ -3255,6 +3261,7 @@ async fn put_reset_password_enrollment(
         PasswordOrOtpData {
             master_password_hash: reset_request.master_password_hash,
             otp: reset_request.otp,
+            sso_step_up_token: None,
         }
         .validate(&headers.user, true, &mut conn)
         .await?;
@@ -3351,3 +3358,267 @@ async fn rotate_api_key(
 ) -> JsonResult {
     _api_key(&org_id, data, true, headers, conn).await
 }
+
+// Trusted device encryption, the device approvals requested to the organization admins
+#[get("/organizations/<org_id>/auth-requests")]
+async fn get_org_auth_requests(org_id: OrganizationId, headers: AdminHeaders, mut conn: DbConn) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+
+    let mut requests = Vec::new();
+    for auth_request in AuthRequest::find_pending_by_org(&org_id, &mut conn).await {
+        let Some(member) = Membership::find_by_user_and_org(&auth_request.user_uuid, &org_id, &mut conn).await else {
+            continue;
+        };
+        let Some(user) = User::find_by_uuid(&auth_request.user_uuid, &mut conn).await else {
+            continue;
+        };
+
+        requests.push(json!({
+            "id": auth_request.uuid,
+            "userId": user.uuid,
+            "organizationUserId": member.uuid,
+            "email": user.email,
+            "publicKey": auth_request.public_key,
+            "requestDeviceIdentifier": auth_request.request_device_identifier,
+            "requestDeviceType": DeviceType::from_i32(auth_request.device_type).to_string(),
+            "requestIpAddress": auth_request.request_ip,
+            "creationDate": crate::util::format_date(&auth_request.creation_date),
+            "object": "pending-org-auth-request",
+        }));
+    }
+
+    Ok(Json(json!({
+        "data": requests,
+        "object": "list",
+        "continuationToken": null,
+    })))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OrgAuthRequestUpdateData {
+    request_approved: bool,
+    encrypted_user_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BulkOrgAuthRequestUpdateData {
+    id: AuthRequestId,
+    request_approved: bool,
+    encrypted_user_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BulkDenyOrgAuthRequestData {
+    ids: Vec<AuthRequestId>,
+}
+
+#[post("/organizations/<org_id>/auth-requests/<auth_request_id>", data = "<data>", rank = 2)]
+async fn post_org_auth_request(
+    org_id: OrganizationId,
+    auth_request_id: AuthRequestId,
+    data: Json<OrgAuthRequestUpdateData>,
+    headers: AdminHeaders,
+    mut conn: DbConn,
+    ant: AnonymousNotify<'_>,
+    nt: Notify<'_>,
+) -> EmptyResult {
+    let data = data.into_inner();
+    update_org_auth_request(
+        &org_id,
+        &auth_request_id,
+        data.request_approved,
+        data.encrypted_user_key,
+        &headers,
+        &mut conn,
+        &ant,
+        &nt,
+    )
+    .await
+}
+
+#[post("/organizations/<org_id>/auth-requests", data = "<data>")]
+async fn post_org_auth_requests(
+    org_id: OrganizationId,
+    data: Json<Vec<BulkOrgAuthRequestUpdateData>>,
+    headers: AdminHeaders,
+    mut conn: DbConn,
+    ant: AnonymousNotify<'_>,
+    nt: Notify<'_>,
+) -> EmptyResult {
+    for update in data.into_inner() {
+        update_org_auth_request(
+            &org_id,
+            &update.id,
+            update.request_approved,
+            update.encrypted_user_key,
+            &headers,
+            &mut conn,
+            &ant,
+            &nt,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[post("/organizations/<org_id>/auth-requests/deny", data = "<data>")]
+async fn post_deny_org_auth_requests(
+    org_id: OrganizationId,
+    data: Json<BulkDenyOrgAuthRequestData>,
+    headers: AdminHeaders,
+    mut conn: DbConn,
+    ant: AnonymousNotify<'_>,
+    nt: Notify<'_>,
+) -> EmptyResult {
+    for auth_request_id in data.into_inner().ids {
+        update_org_auth_request(&org_id, &auth_request_id, false, None, &headers, &mut conn, &ant, &nt).await?;
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn update_org_auth_request(
+    org_id: &OrganizationId,
+    auth_request_id: &AuthRequestId,
+    approved: bool,
+    encrypted_user_key: Option<String>,
+    headers: &AdminHeaders,
+    conn: &mut DbConn,
+    ant: &AnonymousNotify<'_>,
+    nt: &Notify<'_>,
+) -> EmptyResult {
+    if org_id != &headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+
+    let Some(mut auth_request) = AuthRequest::find_by_uuid_and_org(auth_request_id, org_id, conn).await else {
+        err!("AuthRequest doesn't exist", "Record not found or organization id does not match")
+    };
+
+    if auth_request.approved.is_some() {
+        err!("The authentication request was already answered")
+    }
+
+    let Some(member) = Membership::find_by_user_and_org(&auth_request.user_uuid, org_id, conn).await else {
+        err!("The requesting user isn't member of the organization")
+    };
+
+    if !can_approve_device(headers.membership_type, member.atype) {
+        err!("No permission to approve this user device")
+    }
+
+    let event_type = if approved {
+        let Some(encrypted_user_key) = encrypted_user_key else {
+            err!("The encrypted user key is required to approve a device")
+        };
+        auth_request.enc_key = Some(encrypted_user_key);
+        auth_request.response_device_id = Some(headers.device.uuid.clone());
+        EventType::OrganizationUserApprovedAuthRequest
+    } else {
+        EventType::OrganizationUserRejectedAuthRequest
+    };
+
+    // A denied request is kept to let the client know, it will be purged with the expired ones
+    auth_request.approved = Some(approved);
+    auth_request.response_date = Some(chrono::Utc::now().naive_utc());
+    auth_request.save(conn).await?;
+
+    ant.send_auth_response(&auth_request.user_uuid, &auth_request.uuid).await;
+    nt.send_auth_response(&auth_request.user_uuid, &auth_request.uuid, &headers.device, conn).await;
+
+    log_event(event_type as i32, &member.uuid, org_id, &headers.user.uuid, headers.device.atype, &headers.ip.ip, conn)
+        .await;
+
+    Ok(())
+}
+
+// Like Bitwarden, the Require SSO policy depends on the Single Org policy
+async fn check_require_sso_policy(
+    org_id: &OrganizationId,
+    pol_type_enum: OrgPolicyType,
+    enabled: bool,
+    conn: &mut DbConn,
+) -> EmptyResult {
+    if pol_type_enum == OrgPolicyType::RequireSso && enabled {
+        if !CONFIG.sso_enabled() {
+            err!("SSO is not enabled on this server")
+        }
+
+        let single_org_policy_enabled =
+            OrgPolicy::find_by_org_and_type(org_id, OrgPolicyType::SingleOrg, conn).await.is_some_and(|p| p.enabled);
+        if !single_org_policy_enabled {
+            err!("Single Organization policy is not enabled. It is mandatory for this policy to be enabled.")
+        }
+    }
+
+    if pol_type_enum == OrgPolicyType::SingleOrg && !enabled {
+        let require_sso_policy_enabled =
+            OrgPolicy::find_by_org_and_type(org_id, OrgPolicyType::RequireSso, conn).await.is_some_and(|p| p.enabled);
+        if require_sso_policy_enabled {
+            err!("Require single sign-on authentication policy is enabled. It is not allowed to disable this policy.")
+        }
+    }
+
+    Ok(())
+}
+
+// Same rule as the account recovery, the approving admin must be higher/equal to the user
+fn can_approve_device(approver_type: MembershipType, member_type: i32) -> bool {
+    approver_type == MembershipType::Owner || member_type <= MembershipType::Admin
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(sqlite)]
+    use crate::db::test_conn;
+
+    #[cfg(sqlite)]
+    #[rocket::async_test]
+    async fn test_require_sso_policy() {
+        let mut conn = test_conn().await;
+        let org = Organization::new("require sso".to_string(), "billing@example.com".to_string(), None, None);
+        org.save(&mut conn).await.unwrap();
+
+        // Only with the Single Org policy
+        let err = check_require_sso_policy(&org.uuid, OrgPolicyType::RequireSso, true, &mut conn).await.unwrap_err();
+        assert!(err.message().contains("Single Organization policy is not enabled"));
+        assert!(check_require_sso_policy(&org.uuid, OrgPolicyType::RequireSso, false, &mut conn).await.is_ok());
+
+        OrgPolicy::new(org.uuid.clone(), OrgPolicyType::SingleOrg, true, "null".to_string())
+            .save(&mut conn)
+            .await
+            .unwrap();
+        assert!(check_require_sso_policy(&org.uuid, OrgPolicyType::RequireSso, true, &mut conn).await.is_ok());
+        assert!(check_require_sso_policy(&org.uuid, OrgPolicyType::SingleOrg, false, &mut conn).await.is_ok());
+
+        // Which can't be disabled anymore
+        OrgPolicy::new(org.uuid.clone(), OrgPolicyType::RequireSso, true, "null".to_string())
+            .save(&mut conn)
+            .await
+            .unwrap();
+        let err = check_require_sso_policy(&org.uuid, OrgPolicyType::SingleOrg, false, &mut conn).await.unwrap_err();
+        assert!(err.message().contains("not allowed to disable"));
+        assert!(check_require_sso_policy(&org.uuid, OrgPolicyType::SingleOrg, true, &mut conn).await.is_ok());
+    }
+
+    #[test]
+    fn test_can_approve_device() {
+        let (owner, admin, user, manager) =
+            (MembershipType::Owner, MembershipType::Admin, MembershipType::User, MembershipType::Manager);
+        for member in [owner, admin, user, manager] {
+            assert!(can_approve_device(owner, member as i32));
+        }
+
+        // An admin cannot approve the device of an owner
+        assert!(!can_approve_device(admin, owner as i32));
+        for member in [admin, user, manager] {
+            assert!(can_approve_device(admin, member as i32));
+        }
+    }
+}
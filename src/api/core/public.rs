@@ -99,7 +99,7 @@ async fn ldap_import(data: Json<OrgImportData>, token: PublicToken, mut conn: Db
                     new_user
                 }
             };
-            let member_status = if CONFIG.mail_enabled() || user.password_hash.is_empty() {
+            let member_status = if CONFIG.mail_enabled() || !user.is_registered() {
                 MembershipStatus::Invited as i32
             } else {
                 MembershipStatus::Accepted as i32 // Automatically mark user as accepted if no email invites
@@ -31,6 +31,13 @@ static ANON_PUSH_DEVICE: Lazy<Device> = Lazy::new(|| {
         push_token: None,
         refresh_token: String::new(),
         twofactor_remember: None,
+        encrypted_user_key: None,
+        encrypted_public_key: None,
+        encrypted_private_key: None,
+        sso_expires_at: None,
+        sso_login: false,
+        sso_sid: None,
+        sso_token: None,
     }
 });
 
@@ -65,6 +65,7 @@ async fn activate_authenticator(data: Json<EnableAuthenticatorData>, headers: He
     PasswordOrOtpData {
         master_password_hash: data.master_password_hash,
         otp: data.otp,
+        sso_step_up_token: None,
     }
     .validate(&user, true, &mut conn)
     .await?;
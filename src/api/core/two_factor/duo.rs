@@ -165,6 +165,7 @@ async fn activate_duo(data: Json<EnableDuoData>, headers: Headers, mut conn: DbC
     PasswordOrOtpData {
         master_password_hash: data.master_password_hash.clone(),
         otp: data.otp.clone(),
+        sso_step_up_token: None,
     }
     .validate(&user, true, &mut conn)
     .await?;
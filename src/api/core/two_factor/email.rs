@@ -116,6 +116,7 @@ async fn send_email(data: Json<SendEmailData>, headers: Headers, mut conn: DbCon
     PasswordOrOtpData {
         master_password_hash: data.master_password_hash,
         otp: data.otp,
+        sso_step_up_token: None,
     }
     .validate(&user, false, &mut conn)
     .await?;
@@ -161,6 +162,7 @@ async fn email(data: Json<EmailData>, headers: Headers, mut conn: DbConn) -> Jso
     PasswordOrOtpData {
         master_password_hash: data.master_password_hash,
         otp: data.otp,
+        sso_step_up_token: None,
     }
     .validate(&user, true, &mut conn)
     .await?;
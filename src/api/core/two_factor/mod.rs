@@ -143,6 +143,7 @@ async fn disable_twofactor(data: Json<DisableTwoFactorData>, headers: Headers, m
     PasswordOrOtpData {
         master_password_hash: data.master_password_hash,
         otp: data.otp,
+        sso_step_up_token: None,
     }
     .validate(&user, true, &mut conn)
     .await?;
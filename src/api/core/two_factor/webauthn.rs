@@ -245,6 +245,7 @@ async fn activate_webauthn(data: Json<EnableWebauthnData>, headers: Headers, mut
     PasswordOrOtpData {
         master_password_hash: data.master_password_hash,
         otp: data.otp,
+        sso_step_up_token: None,
     }
     .validate(&user, true, &mut conn)
     .await?;
@@ -123,6 +123,7 @@ async fn activate_yubikey(data: Json<EnableYubikeyData>, headers: Headers, mut c
     PasswordOrOtpData {
         master_password_hash: data.master_password_hash.clone(),
         otp: data.otp.clone(),
+        sso_step_up_token: None,
     }
     .validate(&user, true, &mut conn)
     .await?;
@@ -2,8 +2,8 @@ use chrono::{NaiveDateTime, Utc};
 use num_traits::FromPrimitive;
 use rocket::{
     form::{Form, FromForm},
-    http::{CookieJar, Status},
-    response::Redirect,
+    http::{Cookie, CookieJar, SameSite, Status},
+    response::{content::RawHtml as Html, Redirect},
     serde::json::Json,
     Route,
 };
@@ -14,20 +14,22 @@ use crate::{
         admin,
         core::{
             accounts::{PreloginData, RegisterData, _prelogin, _register, kdf_upgrade},
-            log_user_event,
+            log_event, log_login_event,
             two_factor::{authenticator, duo, duo_oidc, email, enforce_2fa_policy, webauthn, yubikey},
+            SsoEventDetails,
         },
         master_password_policy,
         push::register_push_device,
         ApiResult, EmptyResult, JsonResult,
     },
     auth,
-    auth::{generate_organization_api_key_login_claims, AuthMethod, ClientHeaders, ClientIp, ClientVersion},
+    auth::{generate_organization_api_key_login_claims, AuthMethod, ClientHeaders, ClientIp, ClientVersion, Headers},
+    business::organization_logic,
     db::{models::*, DbConn},
     error::MapResult,
     mail, sso,
     sso::{OIDCCode, OIDCState},
-    util, CONFIG,
+    sso_claims, util, CONFIG,
 };
 
 pub fn routes() -> Vec<Route> {
@@ -41,7 +43,17 @@ pub fn routes() -> Vec<Route> {
         prevalidate,
         authorize,
         oidcsignin,
-        oidcsignin_error
+        oidcsignin_without_state,
+        oidcsignin_error,
+        oidcsignin_error_stateless,
+        oidcsignin_form_post,
+        sso_sector_identifier,
+        sso_link_page,
+        sso_link,
+        sso_logout,
+        sso_backchannel_logout,
+        sso_step_up,
+        sso_step_up_verify
     ]
 }
 
@@ -56,6 +68,7 @@ async fn login(
     let data: ConnectData = data.into_inner();
 
     let mut user_id: Option<UserId> = None;
+    let mut sso_event: Option<SsoEventDetails> = None;
 
     let login_result = match data.grant_type.as_ref() {
         "refresh_token" => {
@@ -94,7 +107,7 @@ async fn login(
             _check_is_some(&data.device_name, "device_name cannot be blank")?;
             _check_is_some(&data.device_type, "device_type cannot be blank")?;
 
-            _sso_login(data, &mut user_id, &mut conn, cookies, &client_header.ip, &client_version).await
+            _sso_login(data, &mut user_id, &mut sso_event, &mut conn, cookies, &client_header.ip, &client_version).await
         }
         "authorization_code" => err!("SSO sign-in is not available"),
         t => err!("Invalid type", t),
@@ -103,22 +116,24 @@ async fn login(
     if let Some(user_id) = user_id {
         match &login_result {
             Ok(_) => {
-                log_user_event(
+                log_login_event(
                     EventType::UserLoggedIn as i32,
                     &user_id,
                     client_header.device_type,
                     &client_header.ip.ip,
+                    sso_event.as_ref(),
                     &mut conn,
                 )
                 .await;
             }
             Err(e) => {
                 if let Some(ev) = e.get_event() {
-                    log_user_event(
+                    log_login_event(
                         ev.event as i32,
                         &user_id,
                         client_header.device_type,
                         &client_header.ip.ip,
+                        sso_event.as_ref(),
                         &mut conn,
                     )
                     .await
@@ -165,35 +180,50 @@ async fn _refresh_login(data: ConnectData, conn: &mut DbConn, ip: &ClientIp) ->
     }
 }
 
-// After exchanging the code we need to check first if 2FA is needed before continuing
-async fn _sso_login(
-    data: ConnectData,
-    user_id: &mut Option<UserId>,
-    conn: &mut DbConn,
-    cookies: &CookieJar<'_>,
-    ip: &ClientIp,
-    client_version: &Option<ClientVersion>,
-) -> JsonResult {
-    AuthMethod::Sso.check_scope(data.scope.as_ref())?;
-
-    // Ratelimit the login
-    crate::ratelimit::check_limit_login(&ip.ip)?;
-
-    let code = match data.code.as_ref() {
-        None => err!(
-            "Got no code in OIDC data",
-            ErrorEvent {
-                event: EventType::UserFailedLogIn
+// Look for an account using the primary email then the verified aliases, in order.
+// The emails are normalized with a punycode domain, accounts saved with the Unicode form are also looked for.
+async fn sso_user_by_mails(user_infos: &sso::UserInformation, conn: &DbConn) -> Option<(User, Option<SsoUser>)> {
+    for email in std::iter::once(&user_infos.email).chain(user_infos.email_aliases.iter()) {
+        let found = match SsoUser::find_by_mail(email, conn).await {
+            None => match sso_claims::unicode_email(email) {
+                Some(unicode) => SsoUser::find_by_mail(&unicode, conn).await,
+                None => None,
+            },
+            found => found,
+        };
+        if let Some(found) = found {
+            if *email != user_infos.email {
+                info!(
+                    "SSO identity {} matched the account {} using the alias {email}",
+                    user_infos.identifier, found.0.uuid
+                );
             }
-        ),
-        Some(code) => code,
-    };
+            return Some(found);
+        }
+    }
+    None
+}
 
-    let user_infos = sso::exchange_code(code, conn).await?;
-    let user_with_sso = match SsoUser::find_by_identifier(&user_infos.identifier, conn).await {
-        None => match SsoUser::find_by_mail(&user_infos.email, conn).await {
+// The stable `{iss}/{sub}` identifier is always used first, the email is only used for the initial association.
+// If the provider now returns another email for a known identifier the user is still the same (see `send_sso_change_email`).
+async fn resolve_sso_user(
+    user_infos: &sso::UserInformation,
+    user_id: &mut Option<UserId>,
+    conn: &DbConn,
+) -> ApiResult<Option<(User, Option<SsoUser>)>> {
+    Ok(match SsoUser::find_by_identifier(&user_infos.identifier, conn).await {
+        None => match sso_user_by_mails(user_infos, conn).await {
             None => None,
+            // The identifier is only replaced once the login succeeded
+            Some((user, Some(sso_user))) if sso::subject_migration(&sso_user.identifier, user_infos) => {
+                warn!(
+                    "SSO identity of user {} changed from {} to {}, migrating with `SSO_SUBJECT_MIGRATION`",
+                    user.uuid, sso_user.identifier, user_infos.identifier
+                );
+                Some((user, Some(sso_user)))
+            }
             Some((user, Some(_))) => {
+                *user_id = Some(user.uuid.clone());
                 error!(
                     "Login failure ({}), existing SSO user ({}) with same email ({})",
                     user_infos.identifier, user.uuid, user.email
@@ -205,7 +235,11 @@ async fn _sso_login(
                     }
                 )
             }
-            Some((user, None)) if user.private_key.is_some() && !CONFIG.sso_signups_match_email() => {
+            Some((user, None))
+                if user.private_key.is_some()
+                    && sso::AccountLinking::from_config() == sso::AccountLinking::Disallow =>
+            {
+                *user_id = Some(user.uuid.clone());
                 error!(
                     "Login failure ({}), existing non SSO user ({}) with same email ({}) and association is disabled",
                     user_infos.identifier, user.uuid, user.email
@@ -220,12 +254,69 @@ async fn _sso_login(
             Some((user, None)) => Some((user, None)),
         },
         Some((user, sso_user)) => Some((user, Some(sso_user))),
-    };
+    })
+}
 
-    let now = Utc::now().naive_utc();
+// Resolve or provision the user and check the 2FA, the refusals are recorded in the SSO audit log by the caller.
+// `reason` is the category of the check in progress, reported in the event of a refusal.
+#[allow(clippy::too_many_arguments)]
+async fn sso_login_user(
+    data: &ConnectData,
+    user_infos: &sso::UserInformation,
+    now: &NaiveDateTime,
+    user_id: &mut Option<UserId>,
+    reason: &mut &'static str,
+    conn: &mut DbConn,
+    ip: &ClientIp,
+    client_version: &Option<ClientVersion>,
+) -> ApiResult<(User, Device, Option<String>, Option<SsoUser>, sso::SsoAccount)> {
+    *reason = "account_conflict";
+    let user_with_sso = resolve_sso_user(user_infos, user_id, conn).await?;
+
+    // Set the user_id here to be passed back used for event logging.
+    if let Some((user, _)) = &user_with_sso {
+        *user_id = Some(user.uuid.clone());
+    }
+
+    // Existing master password account matched using the email, the owner must confirm the association first.
+    match &user_with_sso {
+        Some((user, None))
+            if user.private_key.is_some()
+                && user.enabled
+                && sso::AccountLinking::from_config() == sso::AccountLinking::RequireConfirmation =>
+        {
+            if !CONFIG.mail_enabled() {
+                err!(
+                    "An account with the same email exists, the association cannot be confirmed since mail is disabled",
+                    ErrorEvent {
+                        event: EventType::UserFailedLogIn
+                    }
+                )
+            }
+
+            let token = sso::encode_link_claims(&user.uuid, &user_infos.identifier);
+            mail::send_sso_link_confirmation(&user.email, &token).await?;
+            info!("Sent SSO association confirmation to user {} for {}", user.uuid, user_infos.identifier);
+
+            err_silent!(
+                "An account with the same email exists, check your email to confirm the association then log in again",
+                ErrorEvent {
+                    event: EventType::UserFailedLogIn
+                }
+            )
+        }
+        _ => (),
+    }
+
+    let account = match &user_with_sso {
+        None => sso::SsoAccount::Provisioned,
+        Some((_, None)) => sso::SsoAccount::Linked,
+        Some((_, Some(_))) => sso::SsoAccount::Existing,
+    };
     // Will trigger 2FA flow if needed
-    let (user, mut device, twofactor_token, sso_user) = match user_with_sso {
+    let (user, device, twofactor_token, sso_user) = match user_with_sso {
         None => {
+            *reason = "provisioning_policy";
             if !CONFIG.is_email_domain_allowed(&user_infos.email) {
                 err!(
                     "Email domain not allowed",
@@ -252,15 +343,17 @@ async fn _sso_login(
                 _ => (),
             }
 
-            let mut user = User::new(user_infos.email, user_infos.user_name);
-            user.verified_at = Some(now);
+            let mut user = User::new(user_infos.email.clone(), user_infos.user_name.clone());
+            user.verified_at = Some(*now);
             user.save(conn).await?;
+            info!("User {} provisioned using SSO ({})", user.uuid, user_infos.identifier);
 
-            let device = get_device(&data, conn, &user).await?;
+            let device = get_device(data, conn, &user).await?;
 
             (user, device, None, None)
         }
         Some((user, _)) if !user.enabled => {
+            *reason = "user_disabled";
             err!(
                 "This user has been disabled",
                 format!("IP: {}. Username: {}.", ip.ip, user.name),
@@ -270,34 +363,114 @@ async fn _sso_login(
             )
         }
         Some((mut user, sso_user)) => {
-            let mut device = get_device(&data, conn, &user).await?;
-            let twofactor_token = twofactor_auth(&user, &data, &mut device, ip, client_version, conn).await?;
+            let mut device = get_device(data, conn, &user).await?;
+            let twofactor_token = if user_infos.provider_mfa {
+                info!("User {} 2FA satisfied by the SSO provider authentication", user.uuid);
+                None
+            } else {
+                *reason = "two_factor";
+                twofactor_auth(&user, data, &mut device, ip, client_version, conn).await?
+            };
 
             if user.private_key.is_none() {
                 // User was invited a stub was created
-                user.verified_at = Some(now);
-                if let Some(user_name) = user_infos.user_name {
-                    user.name = user_name;
+                user.verified_at = Some(*now);
+                if let Some(ref user_name) = user_infos.user_name {
+                    user.name = user_name.clone();
                 }
 
                 user.save(conn).await?;
             }
 
-            if user.email != user_infos.email {
+            let user_email = sso_claims::normalize_email(&user.email).unwrap_or_else(|_| user.email.to_lowercase());
+            if user_email != user_infos.email && !user_infos.email_aliases.contains(&user_email) {
                 if CONFIG.mail_enabled() {
                     mail::send_sso_change_email(&user_infos.email).await?;
                 }
-                info!("User {} email changed in SSO provider from {} to {}", user.uuid, user.email, user_infos.email);
+                // The email is the salt of the master key, it cannot be changed without the client re-encrypting the `key`.
+                warn!(
+                    "User {} email changed in SSO provider from {} to {}, keeping the old email until the user changes it",
+                    user.uuid, user.email, user_infos.email
+                );
             }
 
             (user, device, twofactor_token, sso_user)
         }
     };
 
+    Ok((user, device, twofactor_token, sso_user, account))
+}
+
+fn sso_event_details(user_infos: &sso::UserInformation, detail: &'static str) -> SsoEventDetails {
+    SsoEventDetails {
+        provider: sso::provider_slug(&user_infos.issuer),
+        subject: user_infos.subject().to_string(),
+        detail,
+    }
+}
+
+// After exchanging the code we need to check first if 2FA is needed before continuing
+async fn _sso_login(
+    data: ConnectData,
+    user_id: &mut Option<UserId>,
+    sso_event: &mut Option<SsoEventDetails>,
+    conn: &mut DbConn,
+    cookies: &CookieJar<'_>,
+    ip: &ClientIp,
+    client_version: &Option<ClientVersion>,
+) -> JsonResult {
+    AuthMethod::Sso.check_scope(data.scope.as_ref())?;
+
+    // Ratelimit the login
+    crate::ratelimit::check_limit_login(&ip.ip)?;
+    crate::ratelimit::check_sso_failures(&ip.ip)?;
+
+    let code = match data.code.as_ref() {
+        None => err!(
+            "Got no code in OIDC data",
+            ErrorEvent {
+                event: EventType::UserFailedLogIn
+            }
+        ),
+        Some(code) => code,
+    };
+
+    let user_infos = sso::exchange_code(code, conn).await.inspect_err(|_| crate::ratelimit::sso_failure(&ip.ip))?;
+
+    let now = Utc::now().naive_utc();
+    let mut reason = "";
+    let (user, mut device, twofactor_token, sso_user, account) =
+        match sso_login_user(&data, &user_infos, &now, user_id, &mut reason, conn, ip, client_version).await {
+            Ok(login) => login,
+            Err(err) => {
+                *sso_event = Some(sso_event_details(&user_infos, reason));
+                sso::audit_refused_login(&user_infos, err.message(), conn).await;
+                return Err(err);
+            }
+        };
+
     // We passed 2FA get full user informations
-    let auth_user = sso::redeem(&user_infos.state, conn).await?;
+    let client = sso::RedeemingClient {
+        device_id: data.device_identifier.as_ref(),
+        client_type: data.client_id.as_deref(),
+    };
+    let redeemed = match sso::redeem(&user_infos.state, &user, account, client, conn).await {
+        Ok(redeemed) => redeemed,
+        Err(err) => {
+            crate::ratelimit::sso_failure(&ip.ip);
+            *sso_event = Some(sso_event_details(&user_infos, "redeem"));
+            return Err(err);
+        }
+    };
+    *sso_event = Some(sso_event_details(&user_infos, redeemed.account.label()));
+
+    info!("User {} logged in using SSO ({}, {:?})", user.uuid, user_infos.identifier, redeemed.account);
 
-    if sso_user.is_none() {
+    if sso_user.as_ref().is_none_or(|sso_user| sso_user.identifier != user_infos.identifier) {
+        if let Some(previous) = sso_user {
+            SsoUser::delete(&user.uuid, conn).await?;
+            info!("User {} SSO identity migrated from {} to {}", user.uuid, previous.identifier, user_infos.identifier);
+        }
         let user_sso = SsoUser {
             user_uuid: user.uuid.clone(),
             identifier: user_infos.identifier,
@@ -308,25 +481,71 @@ async fn _sso_login(
     // Set the user_uuid here to be passed back used for event logging.
     *user_id = Some(user.uuid.clone());
 
-    if let Err(err) = sso::sync_organizations(&user, &auth_user, &device, ip, conn).await {
+    if let Err(err) = sso::sync_organizations(&user, &redeemed.auth_user, &device, ip, conn).await {
         error!("Failure during sso organization sync: {err}");
     }
 
-    if auth_user.is_admin() {
+    if redeemed.newly_provisioned() {
+        if let Err(err) = sso::enroll_default_org(&user, &device, ip, conn).await {
+            error!("Failure when enrolling user {} in the default organization: {err}", user.uuid);
+        }
+    }
+
+    // The invitations were sent to `user.email`, only accept them if the provider verified this same email
+    let user_email = sso_claims::normalize_email(&user.email).unwrap_or_else(|_| user.email.to_lowercase());
+    let email_verified = (redeemed.auth_user.email_verified == Some(true) && redeemed.tokens.email == user_email)
+        || redeemed.auth_user.email_aliases.contains(&user_email);
+    if CONFIG.sso_auto_accept_invites() && email_verified {
+        if let Err(err) = organization_logic::accept_sso_invites(&user, conn).await {
+            error!("Failure when accepting the invitations of user {}: {err}", user.uuid);
+        }
+    }
+
+    if redeemed.first_login() {
+        for membership in Membership::find_by_user(&user.uuid, conn).await {
+            log_event(
+                EventType::OrganizationUserFirstSsoLogin as i32,
+                &membership.uuid,
+                &membership.org_uuid,
+                &user.uuid,
+                device.atype,
+                &ip.ip,
+                conn,
+            )
+            .await;
+        }
+    }
+
+    if redeemed.auth_user.is_admin() {
         info!("User {} logged with admin cookie", user.email);
         cookies.add(admin::create_admin_cookie());
     }
 
-    let auth_tokens = sso::create_auth_tokens(
+    let provider_exp = sso::provider_session_expiration(&redeemed.tokens);
+    let mut auth_tokens = sso::create_auth_tokens(
         &device,
         &user,
         data.client_id,
-        auth_user.refresh_token,
-        auth_user.access_token,
-        auth_user.expires_in,
+        redeemed.tokens.refresh_token,
+        redeemed.tokens.access_token,
+        redeemed.tokens.expires_at,
+        Some(redeemed.tokens.id_token),
     )?;
+    sso::cap_session(&mut device, &mut auth_tokens, provider_exp);
+    device.sso_sid = redeemed.auth_user.sid.clone();
+
+    let mut response = authenticated_response(&user, &mut device, auth_tokens, twofactor_token, &now, conn, ip).await?;
+    response["SsoProvider"] = Value::String(redeemed.auth_user.provider_slug());
+    response["SsoExternalId"] = Value::String(redeemed.auth_user.external_id());
+    if let Some(return_path) = redeemed.return_path {
+        response["SsoReturnPath"] = Value::String(return_path);
+    }
+    Ok(response)
+}
 
-    authenticated_response(&user, &mut device, auth_tokens, twofactor_token, &now, conn, ip).await
+// Require SSO policy, owners and admins are exempted. Like upstream the SSO login itself is not checked.
+async fn sso_login_required(user_uuid: &UserId, conn: &mut DbConn) -> bool {
+    CONFIG.sso_enabled() && OrgPolicy::is_applicable_to_user(user_uuid, OrgPolicyType::RequireSso, None, conn).await
 }
 
 async fn _password_login(
@@ -393,6 +612,14 @@ async fn _password_login(
                 }
             )
         }
+    } else if user.uses_key_connector {
+        err!(
+            "This account uses a Key Connector, log in with SSO",
+            format!("IP: {}. Username: {username}.", ip.ip),
+            ErrorEvent {
+                event: EventType::UserFailedLogIn,
+            }
+        )
     } else if !user.check_valid_password(password) {
         err!(
             "Username or password is incorrect. Try again",
@@ -403,6 +630,16 @@ async fn _password_login(
         )
     }
 
+    if sso_login_required(&user.uuid, conn).await {
+        err!(
+            "Your organization requires you to log in with SSO",
+            format!("IP: {}. Username: {username}.", ip.ip),
+            ErrorEvent {
+                event: EventType::UserFailedLogIn,
+            }
+        )
+    }
+
     // Change the KDF Iterations (only when not logging in with an auth request)
     if data.auth_request.is_none() {
         kdf_upgrade(&mut user, password, conn).await?;
@@ -482,6 +719,11 @@ async fn authenticated_response(
     }
 
     // Save to update `device.updated_at` to track usage and toggle new status
+    device.sso_login = auth_tokens.refresh_claims.sub == AuthMethod::Sso;
+    device.sso_token = sso::device_token(&auth_tokens);
+    if !device.sso_login {
+        device.sso_sid = None;
+    }
     device.save(conn).await?;
 
     let mp_policy = master_password_policy(user, conn).await;
@@ -506,6 +748,20 @@ async fn authenticated_response(
         },
     });
 
+    // Without a `Key` the client generates the master key and pushes it to the Key Connector, otherwise it pulls it
+    if auth_tokens.refresh_claims.sub == AuthMethod::Sso || user.uses_key_connector {
+        if let Some(url) = sso::user_key_connector_url(&user.uuid, conn).await {
+            result["KeyConnectorUrl"] = Value::String(url.clone());
+            result["UserDecryptionOptions"]["KeyConnectorOption"] = json!({ "KeyConnectorUrl": url });
+        }
+    }
+
+    if auth_tokens.refresh_claims.sub == AuthMethod::Sso {
+        if let Some(option) = sso::trusted_device_option(user, device, conn).await {
+            result["UserDecryptionOptions"]["TrustedDeviceOption"] = option;
+        }
+    }
+
     if !user.akey.is_empty() {
         result["Key"] = Value::String(user.akey.clone());
     }
@@ -604,6 +860,9 @@ async fn _user_api_key_login(
     let access_claims = auth::LoginJwtClaims::default(&device, &user, &AuthMethod::UserApiKey, data.client_id);
 
     // Save to update `device.updated_at` to track usage and toggle new status
+    device.sso_login = false;
+    device.sso_sid = None;
+    device.sso_token = None;
     device.save(conn).await?;
 
     info!("User {} logged in successfully via API key. IP: {}", user.email, ip.ip);
@@ -1027,55 +1286,250 @@ fn prevalidate() -> JsonResult {
     }
 }
 
+type CallbackResult = Result<Redirect, (Status, Html<String>)>;
+
+// The state was encoded using Base64 to ensure no issue with providers.
 #[get("/connect/oidc-signin?<code>&<state>", rank = 1)]
-async fn oidcsignin(code: OIDCCode, state: String, conn: DbConn) -> ApiResult<Redirect> {
+async fn oidcsignin(code: OIDCCode, state: String, cookies: &CookieJar<'_>, mut conn: DbConn) -> CallbackResult {
+    _oidcsignin(code, state, Some(cookies), &mut conn).await
+}
+
+// The cookie is only checked when present: a cross-site POST does not carry the `SameSite=Lax` state cookie.
+async fn _oidcsignin(
+    code: OIDCCode,
+    state: String,
+    cookies: Option<&CookieJar<'_>>,
+    conn: &mut DbConn,
+) -> CallbackResult {
+    let raw_state = state.clone();
+    let state = match sso::deocde_state(state) {
+        Ok(state) => state,
+        Err(err) => {
+            error!("SSO callback failed: {err}");
+            return Err(sso_error_page(sso::SsoErrorCategory::SessionExpired, None, None));
+        }
+    };
+
+    let nonce = match sso::find_nonce(&state, conn).await {
+        Some(nonce) => nonce,
+        None => {
+            error!("SSO callback failed: no redirect_uri found for {state}, the flow expired or was already completed");
+            return Err(sso_error_page(sso::SsoErrorCategory::SessionExpired, None, None));
+        }
+    };
+    if let Some(cookies) = cookies {
+        if !check_sso_state_cookie(cookies, &raw_state, &nonce) {
+            return Err(sso_error_page(sso::SsoErrorCategory::SessionExpired, None, nonce.correlation_id.clone()));
+        }
+    }
+
     oidcsignin_redirect(
-        state,
-        |decoded_state| sso::OIDCCodeWrapper::Ok {
-            state: decoded_state,
+        sso::OIDCCodeWrapper::Ok {
+            state,
             code,
         },
-        &conn,
+        &nonce,
     )
-    .await
+    .map_err(|err| {
+        error!("SSO flow {} callback failed: {err}", sso::correlation_id(Some(&nonce)));
+        sso_error_page(sso::SsoErrorCategory::Configuration, None, nonce.correlation_id.clone())
+    })
 }
 
-// Bitwarden client appear to only care for code and state so we pipe it through
-// cf: https://github.com/bitwarden/clients/blob/8e46ef1ae5be8b62b0d3d0b9d1b1c62088a04638/libs/angular/src/auth/components/sso.component.ts#L68C11-L68C23)
+// A code without state (login started from the provider), nothing binds it to this browser and it is never exchanged.
+#[get("/connect/oidc-signin?<code>", rank = 3)]
+fn oidcsignin_without_state(code: OIDCCode) -> CallbackResult {
+    drop(code);
+    Err(_oidcsignin_without_state())
+}
+
+fn _oidcsignin_without_state() -> (Status, Html<String>) {
+    error!("SSO callback received a code without state, logins started from the provider are not supported");
+    sso_error_page(sso::SsoErrorCategory::SessionExpired, None, None)
+}
+
+// The provider returned an error, display it instead of redirecting to the client.
 #[get("/connect/oidc-signin?<state>&<error>&<error_description>", rank = 2)]
 async fn oidcsignin_error(
     state: String,
     error: String,
     error_description: Option<String>,
-    conn: DbConn,
-) -> ApiResult<Redirect> {
-    oidcsignin_redirect(
-        state,
-        |decoded_state| sso::OIDCCodeWrapper::Error {
-            state: decoded_state,
-            error,
-            error_description,
-        },
-        &conn,
-    )
-    .await
+    mut conn: DbConn,
+) -> (Status, Html<String>) {
+    _oidcsignin_error(Some(state), error, error_description, &mut conn).await
 }
 
-// The state was encoded using Base64 to ensure no issue with providers.
-// iss and scope parameters are needed for redirection to work on IOS.
-async fn oidcsignin_redirect(
-    base64_state: String,
-    wrapper: impl FnOnce(OIDCState) -> sso::OIDCCodeWrapper,
-    conn: &DbConn,
-) -> ApiResult<Redirect> {
-    let state = sso::deocde_state(base64_state)?;
-    let code = sso::encode_code_claims(wrapper(state.clone()));
+// Error without a state, the provider could not read the authorization request or the flow was not started here.
+#[get("/connect/oidc-signin?<error>&<error_description>", rank = 4)]
+async fn oidcsignin_error_stateless(
+    error: String,
+    error_description: Option<String>,
+    mut conn: DbConn,
+) -> (Status, Html<String>) {
+    _oidcsignin_error(None, error, error_description, &mut conn).await
+}
 
-    let nonce = match SsoNonce::find(&state, conn).await {
-        Some(n) => n,
-        None => err!(format!("Failed to retrive redirect_uri with {state}")),
+async fn _oidcsignin_error(
+    state: Option<String>,
+    error: String,
+    error_description: Option<String>,
+    conn: &mut DbConn,
+) -> (Status, Html<String>) {
+    crate::metrics::sso_exchange_failure(crate::metrics::ExchangeFailure::ProviderError);
+    let category = sso::SsoErrorCategory::from_provider_error(&error);
+
+    let description = sso::SsoErrorCategory::provider_message(&error);
+
+    let Some(state) = state.and_then(|state| sso::deocde_state(state).ok()) else {
+        error!("SSO provider returned an error without a valid state: {error}, {error_description:?}");
+        return sso_error_page_with(category, description, None, None);
     };
 
+    // The pending flow is removed, the retry link starts a new one
+    let nonce = sso::take_nonce(&state, conn).await;
+    error!(
+        "SSO flow {} failed at the provider: {error}, {}",
+        sso::correlation_id(nonce.as_ref()),
+        error_description.unwrap_or_default()
+    );
+
+    match nonce {
+        None => sso_error_page_with(category, description, None, None),
+        Some(nonce) => {
+            let retry_url = sso::retry_url(&state, &nonce.redirect_uri);
+            sso_error_page_with(category, description, retry_url, nonce.correlation_id)
+        }
+    }
+}
+
+#[derive(FromForm)]
+struct OidcSigninData {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+// `SSO_RESPONSE_MODE=form_post`, the provider auto-submits the same parameters in the body.
+// As with the redirect the request is only trusted because of the state bound to the pending flow.
+#[post("/connect/oidc-signin", data = "<data>")]
+async fn oidcsignin_form_post(data: Form<OidcSigninData>, mut conn: DbConn) -> CallbackResult {
+    let data = data.into_inner();
+    match (data.state, data.code, data.error) {
+        (state, _, Some(error)) => Err(_oidcsignin_error(state, error, data.error_description, &mut conn).await),
+        (Some(state), Some(code), None) => _oidcsignin(OIDCCode::from(code), state, None, &mut conn).await,
+        (None, Some(_), None) => Err(_oidcsignin_without_state()),
+        _ => {
+            error!("SSO form_post callback is missing the `state` and `code` or `error` parameters");
+            Err(sso_error_page(sso::SsoErrorCategory::SessionExpired, None, None))
+        }
+    }
+}
+
+fn sso_error_page(
+    category: sso::SsoErrorCategory,
+    retry_url: Option<String>,
+    correlation_id: Option<String>,
+) -> (Status, Html<String>) {
+    sso_error_page_with(category, category.description(), retry_url, correlation_id)
+}
+
+fn sso_error_page_with(
+    category: sso::SsoErrorCategory,
+    description: &str,
+    retry_url: Option<String>,
+    correlation_id: Option<String>,
+) -> (Status, Html<String>) {
+    let json = json!({
+        "urlpath": CONFIG.domain_path(),
+        "title": category.title(),
+        "description": description,
+        "retry_url": retry_url,
+        "correlation_id": correlation_id,
+    });
+
+    match CONFIG.render_template("sso_error", &json) {
+        Ok(text) => (Status::BadRequest, Html(text)),
+        Err(err) => {
+            error!("Failed to render the sso_error template: {err}");
+            (Status::BadRequest, Html(category.title().to_string()))
+        }
+    }
+}
+
+// `sector_identifier_uri` to register at the provider, the pairwise `sub` then stays the same for all the listed callbacks
+#[get("/sso/sector-identifier")]
+fn sso_sector_identifier() -> JsonResult {
+    if !CONFIG.sso_enabled() {
+        err!("SSO sign-in is not available")
+    }
+    Ok(Json(json!(CONFIG.sso_sector_redirect_uris_vec())))
+}
+
+// Link from the email sent when the SSO identity matched an existing account.
+// Only display a form, the association is done on submit to prevent mail scanners from confirming it.
+#[get("/sso/link?<token>")]
+fn sso_link_page(token: String) -> (Status, Html<String>) {
+    let json = json!({
+        "urlpath": CONFIG.domain_path(),
+        "title": "Confirm SSO association",
+        "description": "Confirm that you just logged in using SSO to associate your account with the SSO provider.",
+        "token": token,
+    });
+    render_sso_link(Status::Ok, &json)
+}
+
+#[derive(FromForm)]
+struct SsoLinkData {
+    token: String,
+}
+
+#[post("/sso/link", data = "<data>")]
+async fn sso_link(data: Form<SsoLinkData>, mut conn: DbConn) -> (Status, Html<String>) {
+    let (status, json) = match sso::confirm_link(&data.token, &mut conn).await {
+        Ok(user) => {
+            info!("User {} confirmed the SSO association", user.uuid);
+            (
+                Status::Ok,
+                json!({
+                    "urlpath": CONFIG.domain_path(),
+                    "title": "Account linked",
+                    "description": "Your account is now associated with your SSO provider, you can log in using SSO.",
+                }),
+            )
+        }
+        Err(err) => {
+            error!("SSO association confirmation failed: {err}");
+            (
+                Status::BadRequest,
+                json!({
+                    "urlpath": CONFIG.domain_path(),
+                    "title": "Link expired or invalid",
+                    "description": "This link cannot be used anymore, log in using SSO again to receive a new one.",
+                }),
+            )
+        }
+    };
+
+    render_sso_link(status, &json)
+}
+
+fn render_sso_link(status: Status, json: &Value) -> (Status, Html<String>) {
+    match CONFIG.render_template("sso_link", json) {
+        Ok(text) => (status, Html(text)),
+        Err(err) => {
+            error!("Failed to render the sso_link template: {err}");
+            (status, Html(json["title"].as_str().unwrap_or_default().to_string()))
+        }
+    }
+}
+
+// Bitwarden client appear to only care for code and state so we pipe it through
+// iss and scope parameters are needed for redirection to work on IOS.
+fn oidcsignin_redirect(wrapper: sso::OIDCCodeWrapper, nonce: &SsoNonce) -> ApiResult<Redirect> {
+    let state = nonce.state.clone();
+    let code = sso::encode_code_claims(wrapper);
+
     let mut url = match url::Url::parse(&nonce.redirect_uri) {
         Ok(url) => url,
         Err(err) => err!(format!("Failed to parse redirect uri ({}): {err}", nonce.redirect_uri)),
@@ -1113,22 +1567,298 @@ struct AuthorizeData {
     response_mode: Option<String>,
     #[allow(unused)]
     domain_hint: Option<String>,
+    #[field(name = uncased("login_hint"))]
+    login_hint: Option<String>,
     #[allow(unused)]
     #[field(name = uncased("ssoToken"))]
     sso_token: Option<String>,
+    #[field(name = uncased("return_path"))]
+    #[field(name = uncased("returnurl"))]
+    return_path: Option<String>,
+    #[field(name = uncased("device_identifier"))]
+    #[field(name = uncased("deviceidentifier"))]
+    device_identifier: Option<DeviceId>,
 }
 
 // The `redirect_uri` will change depending of the client (web, android, ios ..)
 #[get("/connect/authorize?<data..>")]
-async fn authorize(data: AuthorizeData, conn: DbConn) -> ApiResult<Redirect> {
+async fn authorize(data: AuthorizeData, cookies: &CookieJar<'_>, ip: ClientIp, conn: DbConn) -> ApiResult<Redirect> {
+    crate::ratelimit::check_limit_sso(&ip.ip)?;
+
     let AuthorizeData {
         client_id,
         redirect_uri,
         state,
+        login_hint,
+        return_path,
+        device_identifier,
         ..
     } = data;
 
-    let auth_url = sso::authorize_url(state, &client_id, &redirect_uri, conn).await?;
+    let redirect =
+        sso::authorize_url(state, &client_id, &redirect_uri, login_hint, return_path, device_identifier, conn)
+            .await
+            .inspect_err(|_| crate::ratelimit::sso_failure(&ip.ip))?;
 
-    Ok(Redirect::temporary(String::from(auth_url)))
+    // Bind the flow to this browser, the provider redirects back to the callback in the same browser
+    debug!("SSO flow for state {} bound to the browser", redirect.state);
+    cookies.add(sso_state_cookie(redirect.csrf_token.secret().clone()));
+
+    Ok(Redirect::temporary(String::from(redirect.url)))
+}
+
+const SSO_STATE_COOKIE: &str = "VW_SSO_STATE";
+
+fn sso_state_cookie<'a>(csrf_token: String) -> Cookie<'a> {
+    Cookie::build((SSO_STATE_COOKIE, csrf_token))
+        .path(format!("{}/identity/connect/oidc-signin", CONFIG.domain_path()))
+        .max_age(time::Duration::seconds(sso::nonce_lifetime().num_seconds()))
+        // Lax since the provider redirection to the callback is a cross-site navigation
+        .same_site(SameSite::Lax)
+        .http_only(true)
+        .secure(CONFIG.is_https())
+        .into()
+}
+
+// A callback is refused when the browser is bound to another flow (started in another tab or by someone else).
+// Without the cookie (blocked by the browser) the callback is only bound to the pending flow by its state.
+fn check_sso_state_cookie(cookies: &CookieJar<'_>, state: &str, nonce: &SsoNonce) -> bool {
+    let cookie = cookies.get(SSO_STATE_COOKIE).map(|cookie| cookie.value().to_string());
+    if !sso_state_cookie_matches(cookie.as_deref(), state) {
+        warn!(
+            "SSO flow {} refused, the callback state does not match the one bound to this browser",
+            sso::correlation_id(Some(nonce))
+        );
+        return false;
+    }
+
+    if cookie.is_some() {
+        cookies.remove(sso_state_cookie(String::new()));
+    }
+    true
+}
+
+fn sso_state_cookie_matches(cookie: Option<&str>, state: &str) -> bool {
+    cookie.is_none_or(|cookie| crate::crypto::ct_eq(cookie, state))
+}
+
+#[derive(Debug, Clone, Default, FromForm)]
+struct SsoLogoutData {
+    #[field(name = uncased("refresh_token"))]
+    #[field(name = uncased("refreshtoken"))]
+    refresh_token: String,
+    #[field(name = uncased("post_logout_redirect_uri"))]
+    #[field(name = uncased("postlogoutredirecturi"))]
+    post_logout_redirect_uri: Option<String>,
+}
+
+// Return the provider end session url with the `id_token_hint` (null if the provider does not support it).
+// The client is expected to redirect the user to it after clearing its own session.
+#[post("/sso/logout", data = "<data>")]
+async fn sso_logout(data: Form<SsoLogoutData>, mut conn: DbConn) -> JsonResult {
+    let refresh_claims = match auth::decode_refresh(&data.refresh_token) {
+        Err(err) => err_silent!(format!("Impossible to read refresh_token: {}", err.message())),
+        Ok(claims) => claims,
+    };
+
+    if refresh_claims.sub != AuthMethod::Sso {
+        err!("Not an SSO session")
+    }
+
+    if Device::find_by_refresh_token(&refresh_claims.device_token, &mut conn).await.is_none() {
+        err!("Invalid refresh token")
+    }
+
+    // The session is ending, the provider tokens it contains will not be used anymore
+    if let Some(token) = &refresh_claims.token {
+        if let Err(err) = sso::revoke_token(token).await {
+            error!("Failed to revoke the provider token: {err}");
+        }
+    }
+
+    let logout_url = sso::logout_url(refresh_claims.id_token, data.into_inner().post_logout_redirect_uri).await?;
+
+    Ok(Json(json!({
+        "logoutUrl": logout_url.map(String::from),
+    })))
+}
+
+#[derive(FromForm)]
+struct BackchannelLogoutData {
+    logout_token: String,
+}
+
+// OIDC Back-Channel Logout, the provider notifies the end of a session with a signed logout token.
+// Register `{DOMAIN}/identity/sso/backchannel-logout` as the `backchannel_logout_uri` of the client.
+#[post("/sso/backchannel-logout", data = "<data>")]
+async fn sso_backchannel_logout(data: Form<BackchannelLogoutData>, ip: ClientIp, mut conn: DbConn) -> EmptyResult {
+    if !CONFIG.sso_enabled() {
+        err!("SSO sign-in is not available")
+    }
+    crate::ratelimit::check_limit_sso(&ip.ip)?;
+
+    let logout =
+        sso::verify_logout_token(&data.logout_token).await.inspect_err(|_| crate::ratelimit::sso_failure(&ip.ip))?;
+    sso::backchannel_logout(&logout, &mut conn).await
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SsoStepUpData {
+    redirect_uri: String,
+}
+
+// Start a step-up authentication of the logged in user, the client opens the returned url.
+// The provider callback redirects to `redirectUri` with the code to send to `/sso/step-up/verify`.
+#[post("/sso/step-up", data = "<data>")]
+async fn sso_step_up(
+    data: Json<SsoStepUpData>,
+    headers: Headers,
+    cookies: &CookieJar<'_>,
+    ip: ClientIp,
+    conn: DbConn,
+) -> JsonResult {
+    crate::ratelimit::check_limit_sso(&ip.ip)?;
+
+    let redirect = sso::step_up_authorize_url(&headers.user, &data.redirect_uri, conn)
+        .await
+        .inspect_err(|_| crate::ratelimit::sso_failure(&ip.ip))?;
+
+    debug!("SSO step-up for state {} bound to the browser", redirect.state);
+    cookies.add(sso_state_cookie(redirect.csrf_token.secret().clone()));
+
+    Ok(Json(json!({
+        "url": String::from(redirect.url),
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SsoStepUpVerifyData {
+    code: String,
+}
+
+// Return the assertion to send as `ssoStepUpToken` instead of the master password to the protected endpoints
+#[post("/sso/step-up/verify", data = "<data>")]
+async fn sso_step_up_verify(
+    data: Json<SsoStepUpVerifyData>,
+    headers: Headers,
+    ip: ClientIp,
+    mut conn: DbConn,
+) -> JsonResult {
+    crate::ratelimit::check_limit_sso(&ip.ip)?;
+
+    let token = sso::verify_step_up(&data.code, &headers.user, &mut conn)
+        .await
+        .inspect_err(|_| crate::ratelimit::sso_failure(&ip.ip))?;
+
+    Ok(Json(json!({
+        "ssoStepUpToken": token,
+        "expiresIn": sso::STEP_UP_VALIDITY.num_seconds(),
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(sqlite)]
+    use crate::db::test_conn;
+
+    #[cfg(sqlite)]
+    fn user_infos(identifier: &str, email: &str) -> sso::UserInformation {
+        sso::UserInformation {
+            state: OIDCState::from("resolve-state".to_string()),
+            identifier: sso::OIDCIdentifier::from(identifier.to_string()),
+            issuer: "https://idp.example.com".to_string(),
+            email: email.to_string(),
+            email_verified: Some(true),
+            email_aliases: vec![],
+            user_name: None,
+            provider_mfa: false,
+        }
+    }
+
+    #[cfg(sqlite)]
+    #[rocket::async_test]
+    async fn test_resolve_sso_user() {
+        let mut conn = test_conn().await;
+        let mut user_id = None;
+
+        // First login, the invited account is found with its email
+        let mut user = User::new("resolve@example.com".to_string(), None);
+        user.save(&mut conn).await.unwrap();
+        let infos = user_infos("https://idp.example.com/resolve", "resolve@example.com");
+        let (found, sso_user) = resolve_sso_user(&infos, &mut user_id, &conn).await.unwrap().unwrap();
+        assert_eq!(found.uuid, user.uuid);
+        assert!(sso_user.is_none());
+        SsoUser {
+            user_uuid: user.uuid.clone(),
+            identifier: infos.identifier.clone(),
+        }
+        .save(&mut conn)
+        .await
+        .unwrap();
+
+        // Subsequent login, found with the identifier
+        let (found, sso_user) = resolve_sso_user(&infos, &mut user_id, &conn).await.unwrap().unwrap();
+        assert_eq!(found.uuid, user.uuid);
+        assert_eq!(sso_user.unwrap().identifier, infos.identifier);
+
+        // The identifier wins when the provider presents another email, even the one of another account
+        let mut other = User::new("resolve-other@example.com".to_string(), None);
+        other.save(&mut conn).await.unwrap();
+        let changed = user_infos("https://idp.example.com/resolve", "resolve-other@example.com");
+        let (found, _) = resolve_sso_user(&changed, &mut user_id, &conn).await.unwrap().unwrap();
+        assert_eq!(found.uuid, user.uuid);
+
+        // And another identifier with the email of an associated account is refused
+        let hijack = user_infos("https://idp.example.com/hijack", "resolve@example.com");
+        let err = resolve_sso_user(&hijack, &mut user_id, &conn).await.err().unwrap();
+        assert!(err.message().contains("Existing SSO user with same email"));
+        assert_eq!(user_id, Some(user.uuid.clone()));
+
+        // Unknown identity and email
+        let unknown = user_infos("https://idp.example.com/unknown", "resolve-unknown@example.com");
+        assert!(resolve_sso_user(&unknown, &mut None, &conn).await.unwrap().is_none());
+    }
+
+    #[cfg(sqlite)]
+    #[rocket::async_test]
+    async fn test_sso_login_required() {
+        let mut conn = test_conn().await;
+        let org = Organization::new("sso required".to_string(), "billing@example.com".to_string(), None, None);
+        org.save(&mut conn).await.unwrap();
+        let mut policy = OrgPolicy::new(org.uuid.clone(), OrgPolicyType::RequireSso, true, "null".to_string());
+        policy.save(&mut conn).await.unwrap();
+
+        let mut users = vec![];
+        for (name, atype) in [("user", MembershipType::User), ("admin", MembershipType::Admin)] {
+            let mut user = User::new(format!("{name}@sso-required.example.com"), None);
+            user.save(&mut conn).await.unwrap();
+            let mut member = Membership::new(user.uuid.clone(), org.uuid.clone(), None);
+            member.atype = atype as i32;
+            member.status = MembershipStatus::Confirmed as i32;
+            member.save(&mut conn).await.unwrap();
+            users.push(user);
+        }
+        let mut outsider = User::new("outsider@sso-required.example.com".to_string(), None);
+        outsider.save(&mut conn).await.unwrap();
+
+        // Owners and admins can still use their master password
+        assert!(sso_login_required(&users[0].uuid, &mut conn).await);
+        assert!(!sso_login_required(&users[1].uuid, &mut conn).await);
+        assert!(!sso_login_required(&outsider.uuid, &mut conn).await);
+
+        policy.enabled = false;
+        policy.save(&mut conn).await.unwrap();
+        assert!(!sso_login_required(&users[0].uuid, &mut conn).await);
+    }
+
+    #[test]
+    fn test_sso_state_cookie_matches() {
+        assert!(sso_state_cookie_matches(Some("state"), "state"));
+        assert!(!sso_state_cookie_matches(Some("other-state"), "state"));
+        // Blocked or not sent (cross-site `form_post`), the state alone binds the callback
+        assert!(sso_state_cookie_matches(None, "state"));
+    }
 }
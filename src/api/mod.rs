@@ -4,6 +4,7 @@ mod icons;
 mod identity;
 mod notifications;
 mod push;
+mod scim;
 mod web;
 
 use rocket::serde::json::Json;
@@ -28,6 +29,7 @@ pub use crate::api::{
         push_cipher_update, push_folder_update, push_logout, push_send_update, push_user_update, register_push_device,
         unregister_push_device,
     },
+    scim::routes as scim_routes,
     web::catchers as web_catchers,
     web::routes as web_routes,
     web::static_files,
@@ -49,6 +51,8 @@ pub type EmptyResult = ApiResult<()>;
 struct PasswordOrOtpData {
     master_password_hash: Option<String>,
     otp: Option<String>,
+    // Recent authentication assertion returned by the SSO step-up
+    sso_step_up_token: Option<String>,
 }
 
 impl PasswordOrOtpData {
@@ -58,6 +62,10 @@ impl PasswordOrOtpData {
     pub async fn validate(&self, user: &User, delete_if_valid: bool, conn: &mut DbConn) -> EmptyResult {
         use crate::api::core::two_factor::protected_actions::validate_protected_action_otp;
 
+        if let Some(token) = self.sso_step_up_token.as_deref() {
+            return crate::sso::validate_step_up(token, user, delete_if_valid);
+        }
+
         match (self.master_password_hash.as_deref(), self.otp.as_deref()) {
             (Some(pw_hash), None) => {
                 if !user.check_valid_password(pw_hash) {
@@ -0,0 +1,525 @@
+//
+// Minimal SCIM 2.0 server (RFC 7643/7644) used by the providers to push the user lifecycle.
+// Users are the Vaultwarden accounts and Groups are mapped onto the organizations.
+//
+use rocket::{
+    http::{ContentType, Status},
+    request::{FromRequest, Outcome, Request},
+    response::{self, Responder, Response},
+    serde::json::Json,
+    Route,
+};
+use serde_json::Value;
+
+use crate::{
+    api::{ApiResult, EmptyResult, Notify},
+    auth::ClientIp,
+    business::user_logic,
+    crypto,
+    db::{models::*, DbConn},
+    sso,
+    util::format_date,
+    CONFIG,
+};
+
+const ACTING_SCIM_USER: &str = "vaultwarden-scim-000000-000000000000";
+
+const USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+const GROUP_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:Group";
+const LIST_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+
+// Page size when the client does not request one
+const DEFAULT_COUNT: usize = 100;
+
+pub fn routes() -> Vec<Route> {
+    routes![get_users, get_user, post_user, patch_user, delete_user, get_groups, get_group, patch_group]
+}
+
+pub struct ScimToken {
+    ip: ClientIp,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ScimToken {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let ip = match ClientIp::from_request(request).await {
+            Outcome::Success(ip) => ip,
+            _ => err_handler!("Error getting Client IP"),
+        };
+
+        let Some(scim_token) = CONFIG.sso_scim_token() else {
+            err_handler!("SCIM provisioning is disabled")
+        };
+
+        // The failures share the SSO failure limit, a blocked IP is refused before the token is checked
+        if crate::ratelimit::check_sso_failures(&ip.ip).is_err() {
+            return Outcome::Error((Status::TooManyRequests, "Too many failed SCIM requests"));
+        }
+
+        let access_token = match request.headers().get_one("Authorization").and_then(|a| a.strip_prefix("Bearer ")) {
+            Some(token) => token,
+            None => {
+                crate::ratelimit::sso_failure(&ip.ip);
+                err_handler!("No SCIM token provided")
+            }
+        };
+
+        if !crypto::ct_eq(scim_token, access_token) {
+            crate::ratelimit::sso_failure(&ip.ip);
+            err_handler!("Invalid SCIM token", format!("IP: {}", ip.ip));
+        }
+
+        Outcome::Success(Self {
+            ip,
+        })
+    }
+}
+
+// SCIM responses use the `application/scim+json` media type
+pub struct Scim(Status, Value);
+
+impl<'r> Responder<'r, 'static> for Scim {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        Response::build_from(Json(self.1).respond_to(request)?)
+            .status(self.0)
+            .header(ContentType::new("application", "scim+json"))
+            .ok()
+    }
+}
+
+fn list_response(resources: Vec<Value>, total: usize, start_index: usize) -> Scim {
+    Scim(
+        Status::Ok,
+        json!({
+            "schemas": [LIST_SCHEMA],
+            "totalResults": total,
+            "startIndex": start_index,
+            "itemsPerPage": resources.len(),
+            "Resources": resources,
+        }),
+    )
+}
+
+// Only the `<attribute> eq "<value>"` filter is supported, it is what the providers use to look up a resource
+fn parse_eq_filter(filter: &str, attribute: &str) -> ApiResult<String> {
+    let mut parts = filter.trim().splitn(3, ' ');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(attr), Some(op), Some(value))
+            if attr.eq_ignore_ascii_case(attribute) && op.eq_ignore_ascii_case("eq") =>
+        {
+            match value.trim().strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+                Some(value) => Ok(value.replace("\\\"", "\"")),
+                None => err_code!(format!("Invalid SCIM filter value: {filter}"), Status::BadRequest.code),
+            }
+        }
+        _ => err_code!(
+            format!("Unsupported SCIM filter, only `{attribute} eq` is supported: {filter}"),
+            Status::BadRequest.code
+        ),
+    }
+}
+
+fn paginate<T>(items: Vec<T>, start_index: Option<usize>, count: Option<usize>) -> (usize, usize, Vec<T>) {
+    let total = items.len();
+    let start_index = start_index.unwrap_or(1).max(1);
+    let count = count.unwrap_or(DEFAULT_COUNT);
+    (total, start_index, items.into_iter().skip(start_index - 1).take(count).collect())
+}
+
+fn location(resource: &str, id: &str) -> String {
+    format!("{}/scim/v2/{resource}/{id}", CONFIG.domain())
+}
+
+fn user_resource(user: &User) -> Value {
+    json!({
+        "schemas": [USER_SCHEMA],
+        "id": user.uuid,
+        "userName": user.email,
+        "displayName": user.name,
+        "name": { "formatted": user.name },
+        "emails": [{ "value": user.email, "primary": true }],
+        "active": user.enabled,
+        "meta": {
+            "resourceType": "User",
+            "created": format_date(&user.created_at),
+            "lastModified": format_date(&user.updated_at),
+            "location": location("Users", &user.uuid),
+        },
+    })
+}
+
+async fn group_resource(org: &Organization, with_members: bool, conn: &mut DbConn) -> Value {
+    let mut members = vec![];
+    if with_members {
+        for user in User::find_by_org(&org.uuid, conn).await {
+            members.push(json!({ "value": user.uuid, "display": user.email }));
+        }
+    }
+
+    json!({
+        "schemas": [GROUP_SCHEMA],
+        "id": org.uuid,
+        "displayName": org.name,
+        "members": members,
+        "meta": {
+            "resourceType": "Group",
+            "location": location("Groups", &org.uuid),
+        },
+    })
+}
+
+async fn get_user_or_404(user_id: &UserId, conn: &mut DbConn) -> ApiResult<User> {
+    match User::find_by_uuid(user_id, conn).await {
+        Some(user) => Ok(user),
+        None => err_code!("User doesn't exist", Status::NotFound.code),
+    }
+}
+
+async fn get_org_or_404(org_id: &OrganizationId, conn: &mut DbConn) -> ApiResult<Organization> {
+    match Organization::find_by_uuid(org_id, conn).await {
+        Some(org) => Ok(org),
+        None => err_code!("Group doesn't exist", Status::NotFound.code),
+    }
+}
+
+// Deactivation disables the account and revokes all its sessions, the vault data is kept
+async fn set_active(user: &mut User, active: bool, nt: &Notify<'_>, conn: &mut DbConn) -> EmptyResult {
+    if user.enabled == active {
+        return Ok(());
+    }
+
+    if active {
+        info!("SCIM enabled user {}", user.uuid);
+        user.enabled = true;
+        user.save(conn).await
+    } else {
+        info!("SCIM disabled user {}", user.uuid);
+        user_logic::disable_user(user, nt, conn).await
+    }
+}
+
+// Providers send booleans or strings (`"False"` for Azure AD)
+fn as_bool(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(b) => Some(*b),
+        Value::String(s) => s.to_lowercase().parse().ok(),
+        _ => None,
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScimName {
+    formatted: Option<String>,
+    given_name: Option<String>,
+    family_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScimUserData {
+    user_name: String,
+    display_name: Option<String>,
+    name: Option<ScimName>,
+    active: Option<bool>,
+}
+
+impl ScimUserData {
+    fn name(&self) -> Option<String> {
+        self.display_name.clone().or_else(|| {
+            let name = self.name.as_ref()?;
+            name.formatted.clone().or_else(|| {
+                let parts: Vec<&str> =
+                    [&name.given_name, &name.family_name].into_iter().flatten().map(String::as_str).collect();
+                (!parts.is_empty()).then(|| parts.join(" "))
+            })
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct PatchOperation {
+    op: String,
+    path: Option<String>,
+    #[serde(default)]
+    value: Value,
+}
+
+#[derive(Deserialize)]
+struct PatchData {
+    #[serde(rename = "Operations")]
+    operations: Vec<PatchOperation>,
+}
+
+#[get("/Users?<filter>&<startIndex>&<count>")]
+#[allow(non_snake_case)]
+async fn get_users(
+    filter: Option<String>,
+    startIndex: Option<usize>,
+    count: Option<usize>,
+    _token: ScimToken,
+    mut conn: DbConn,
+) -> ApiResult<Scim> {
+    let users = match filter {
+        Some(filter) => {
+            let email = parse_eq_filter(&filter, "userName")?;
+            User::find_by_mail(&email, &mut conn).await.into_iter().collect()
+        }
+        None => User::get_all(&mut conn).await.into_iter().map(|(user, _)| user).collect(),
+    };
+
+    let (total, start_index, users) = paginate(users, startIndex, count);
+    Ok(list_response(users.iter().map(user_resource).collect(), total, start_index))
+}
+
+#[get("/Users/<user_id>")]
+async fn get_user(user_id: UserId, _token: ScimToken, mut conn: DbConn) -> ApiResult<Scim> {
+    let user = get_user_or_404(&user_id, &mut conn).await?;
+    Ok(Scim(Status::Ok, user_resource(&user)))
+}
+
+// Create an account without master password, it is associated on the first SSO login like an invited user
+#[post("/Users", data = "<data>")]
+async fn post_user(data: Json<ScimUserData>, _token: ScimToken, mut conn: DbConn) -> ApiResult<Scim> {
+    let data = data.into_inner();
+    let email = data.user_name.trim().to_lowercase();
+
+    if !email.contains('@') {
+        err_code!(format!("Invalid userName, an email is expected: {email}"), Status::BadRequest.code)
+    }
+
+    if User::find_by_mail(&email, &mut conn).await.is_some() {
+        err_code!(format!("User {email} already exists"), Status::Conflict.code)
+    }
+
+    if !CONFIG.is_email_domain_allowed(&email) {
+        err_code!(format!("Email domain not allowed: {email}"), Status::BadRequest.code)
+    }
+
+    let mut user = User::new(email, data.name());
+    user.enabled = data.active.unwrap_or(true);
+    user.save(&mut conn).await?;
+    info!("SCIM provisioned user {}", user.uuid);
+
+    Ok(Scim(Status::Created, user_resource(&user)))
+}
+
+#[patch("/Users/<user_id>", data = "<data>")]
+async fn patch_user(
+    user_id: UserId,
+    data: Json<PatchData>,
+    _token: ScimToken,
+    nt: Notify<'_>,
+    mut conn: DbConn,
+) -> ApiResult<Scim> {
+    let mut user = get_user_or_404(&user_id, &mut conn).await?;
+
+    for operation in data.into_inner().operations {
+        if !operation.op.eq_ignore_ascii_case("replace") && !operation.op.eq_ignore_ascii_case("add") {
+            debug!("Ignoring SCIM {} operation on user {}", operation.op, user.uuid);
+            continue;
+        }
+
+        // Either `{"path": "active", "value": false}` or `{"value": {"active": false}}`
+        let active = match operation.path.as_deref() {
+            Some(path) if path.eq_ignore_ascii_case("active") => as_bool(&operation.value),
+            None => operation.value.get("active").and_then(as_bool),
+            Some(path) => {
+                debug!("Ignoring SCIM update of {path} on user {}", user.uuid);
+                None
+            }
+        };
+
+        if let Some(active) = active {
+            set_active(&mut user, active, &nt, &mut conn).await?;
+        }
+    }
+
+    Ok(Scim(Status::Ok, user_resource(&user)))
+}
+
+// The account is only disabled, its vault can be deleted from the admin panel
+#[delete("/Users/<user_id>")]
+async fn delete_user(user_id: UserId, _token: ScimToken, nt: Notify<'_>, mut conn: DbConn) -> ApiResult<Status> {
+    let mut user = get_user_or_404(&user_id, &mut conn).await?;
+    set_active(&mut user, false, &nt, &mut conn).await?;
+    Ok(Status::NoContent)
+}
+
+#[get("/Groups?<filter>&<startIndex>&<count>&<excludedAttributes>")]
+#[allow(non_snake_case)]
+async fn get_groups(
+    filter: Option<String>,
+    startIndex: Option<usize>,
+    count: Option<usize>,
+    excludedAttributes: Option<String>,
+    _token: ScimToken,
+    mut conn: DbConn,
+) -> ApiResult<Scim> {
+    let orgs = match filter {
+        Some(filter) => {
+            let name = parse_eq_filter(&filter, "displayName")?;
+            Organization::find_by_name(&name, &mut conn).await.into_iter().collect()
+        }
+        None => Organization::get_all(&mut conn).await,
+    };
+
+    let with_members = !excludedAttributes.is_some_and(|ex| ex.split(',').any(|a| a.trim() == "members"));
+    let (total, start_index, orgs) = paginate(orgs, startIndex, count);
+
+    let mut resources = vec![];
+    for org in orgs {
+        resources.push(group_resource(&org, with_members, &mut conn).await);
+    }
+
+    Ok(list_response(resources, total, start_index))
+}
+
+#[get("/Groups/<org_id>")]
+async fn get_group(org_id: OrganizationId, _token: ScimToken, mut conn: DbConn) -> ApiResult<Scim> {
+    let org = get_org_or_404(&org_id, &mut conn).await?;
+    Ok(Scim(Status::Ok, group_resource(&org, true, &mut conn).await))
+}
+
+// Read the user ids of `[{"value": "<id>"}]`
+fn member_ids(value: &Value) -> Vec<UserId> {
+    value
+        .as_array()
+        .map(|members| {
+            members
+                .iter()
+                .filter_map(|m| m.get("value").and_then(Value::as_str))
+                .map(|id| id.to_string().into())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Azure AD removes a member with the `members[value eq "<id>"]` path
+fn member_path_id(path: &str) -> Option<UserId> {
+    let filter = path.strip_prefix("members[")?.strip_suffix(']')?;
+    parse_eq_filter(filter, "value").ok().map(UserId::from)
+}
+
+#[patch("/Groups/<org_id>", data = "<data>")]
+async fn patch_group(
+    org_id: OrganizationId,
+    data: Json<PatchData>,
+    token: ScimToken,
+    mut conn: DbConn,
+) -> ApiResult<Scim> {
+    let org = get_org_or_404(&org_id, &mut conn).await?;
+    let acting_user: UserId = ACTING_SCIM_USER.into();
+    let device = Device::server(acting_user.clone());
+
+    let mut added = vec![];
+    let mut removed = vec![];
+    for operation in data.into_inner().operations {
+        let op = operation.op.to_lowercase();
+        match (op.as_str(), operation.path.as_deref()) {
+            ("add", Some("members")) => added.extend(member_ids(&operation.value)),
+            ("remove", Some("members")) => removed.extend(member_ids(&operation.value)),
+            ("remove", Some(path)) if path.starts_with("members[") => {
+                removed.extend(member_path_id(path));
+            }
+            ("replace", Some("members")) => {
+                let members = member_ids(&operation.value);
+                for mbs in Membership::find_by_org(&org.uuid, &mut conn).await {
+                    if mbs.is_provider_managed() && !members.contains(&mbs.user_uuid) {
+                        removed.push(mbs.user_uuid);
+                    }
+                }
+                added.extend(members);
+            }
+            (op, path) => debug!("Ignoring SCIM {op} operation on group {} ({path:?})", org.uuid),
+        }
+    }
+
+    // Resolve every member before changing anything, an unknown member refuses the whole PATCH
+    let mut to_add = Vec::with_capacity(added.len());
+    for user_id in added {
+        to_add.push(get_user_or_404(&user_id, &mut conn).await?);
+    }
+
+    // The members added by hand are left to the organization admins
+    let mut to_remove = Vec::with_capacity(removed.len());
+    for user_id in removed {
+        let user = get_user_or_404(&user_id, &mut conn).await?;
+        match Membership::find_by_user_and_org(&user.uuid, &org.uuid, &mut conn).await {
+            Some(mbs) if mbs.is_provider_managed() => to_remove.push((user, mbs)),
+            Some(mbs) => {
+                info!("SCIM not removing member {} of organization {}, it was added by hand", mbs.uuid, org.uuid)
+            }
+            None => (),
+        }
+    }
+
+    for user in to_add {
+        let mut mbs =
+            sso::add_org_member(&acting_user, &user, &device, &token.ip, &org, MembershipType::User, &mut conn).await?;
+        if !mbs.scim_managed {
+            mbs.scim_managed = true;
+            mbs.save(&mut conn).await?;
+        }
+    }
+
+    for (user, mbs) in to_remove {
+        sso::revoke_org_member(&acting_user, &user, &device, &token.ip, mbs, &mut conn).await?;
+    }
+
+    Ok(Scim(Status::Ok, group_resource(&org, true, &mut conn).await))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_eq_filter() {
+        assert_eq!(parse_eq_filter(r#"userName eq "jane@example.com""#, "userName").unwrap(), "jane@example.com");
+        assert_eq!(parse_eq_filter(r#"username EQ "Jane Doe""#, "userName").unwrap(), "Jane Doe");
+        assert!(parse_eq_filter(r#"userName sw "jane""#, "userName").is_err());
+        assert!(parse_eq_filter(r#"emails eq "jane@example.com""#, "userName").is_err());
+        assert!(parse_eq_filter("userName eq jane", "userName").is_err());
+
+        assert_eq!(member_path_id(r#"members[value eq "0000-1111"]"#), Some(UserId::from("0000-1111".to_string())));
+        assert_eq!(member_path_id("members"), None);
+    }
+
+    #[cfg(sqlite)]
+    #[rocket::async_test]
+    async fn test_group_members() {
+        let mut conn = crate::db::test_conn().await;
+        let org = Organization::new("scim".to_string(), "billing@example.com".to_string(), None, None);
+        org.save(&mut conn).await.unwrap();
+
+        let mut members = vec![];
+        for email in ["scim-member@example.com", "scim-revoked@example.com"] {
+            let mut user = User::new(email.to_string(), None);
+            user.save(&mut conn).await.unwrap();
+            let mbs = Membership::new(user.uuid.clone(), org.uuid.clone(), None);
+            mbs.save(&mut conn).await.unwrap();
+            members.push(mbs);
+        }
+        members[1].revoke();
+        members[1].save(&mut conn).await.unwrap();
+
+        let group = group_resource(&org, true, &mut conn).await;
+        assert_eq!(group["members"], json!([{ "value": members[0].user_uuid, "display": "scim-member@example.com" }]));
+
+        // Only the members pushed by SCIM or synced on a SSO login can be removed by SCIM
+        assert!(!members[0].is_provider_managed());
+        members[0].scim_managed = true;
+        assert!(members[0].is_provider_managed());
+    }
+
+    #[test]
+    fn test_as_bool() {
+        assert_eq!(as_bool(&json!(false)), Some(false));
+        assert_eq!(as_bool(&json!("False")), Some(false));
+        assert_eq!(as_bool(&json!("true")), Some(true));
+        assert_eq!(as_bool(&json!(1)), None);
+    }
+}
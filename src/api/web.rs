@@ -2,7 +2,7 @@ use std::path::{Path, PathBuf};
 
 use rocket::{
     fs::NamedFile,
-    http::ContentType,
+    http::{ContentType, Status},
     response::{content::RawCss as Css, content::RawHtml as Html, Redirect},
     serde::json::Json,
     Catcher, Route,
@@ -14,6 +14,7 @@ use crate::{
     auth::decode_file_download,
     db::models::{AttachmentId, CipherId},
     error::Error,
+    sso,
     util::Cached,
     CONFIG,
 };
@@ -21,7 +22,7 @@ use crate::{
 pub fn routes() -> Vec<Route> {
     // If adding more routes here, consider also adding them to
     // crate::utils::LOGGED_ROUTES to make sure they appear in the log
-    let mut routes = routes![attachments, alive, alive_head, static_files];
+    let mut routes = routes![attachments, alive, alive_head, alive_sso, static_files];
     if CONFIG.web_vault_enabled() {
         routes.append(&mut routes![web_index, web_index_direct, web_index_head, app_id, web_files, vaultwarden_css]);
     }
@@ -187,6 +188,24 @@ fn alive_head(_conn: DbConn) -> EmptyResult {
     Ok(())
 }
 
+// Readiness check including the SSO provider, responds with a 503 when its discovery failed.
+// The provider is only probed again once the previous result (or the client cache) expired.
+#[get("/alive/sso")]
+async fn alive_sso(_conn: DbConn) -> (Status, Json<Value>) {
+    if !CONFIG.sso_enabled() {
+        return (Status::Ok, Json(json!({ "reachable": false })));
+    }
+
+    // The error is only displayed on the admin diagnostics page, it can contain internal urls
+    let health = sso::provider_health().await;
+    let status = if health.reachable {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
+    };
+    (status, Json(json!({ "reachable": health.reachable, "lastDiscovery": health.last_discovery })))
+}
+
 // This endpoint/function is used during development and development only.
 // It allows to easily develop the admin interface by always loading the files from disk instead from a slice of bytes
 // This will only be active during a debug build and only when `RELOAD_TEMPLATES` is set to `true`
@@ -1,5 +1,6 @@
 // JWT Handling
 use chrono::{DateTime, TimeDelta, Utc};
+use data_encoding::BASE64URL_NOPAD;
 use jsonwebtoken::{errors::ErrorKind, Algorithm, DecodingKey, EncodingKey, Header};
 use num_traits::FromPrimitive;
 use once_cell::sync::{Lazy, OnceCell};
@@ -15,6 +16,7 @@ use std::{
 
 use crate::{
     api::ApiResult,
+    crypto,
     db::models::{
         AttachmentId, CipherId, CollectionId, DeviceId, DeviceType, EmergencyAccessId, MembershipId, OrgApiKeyId,
         OrganizationId, SendFileId, SendId, UserId,
@@ -47,6 +49,7 @@ static JWT_REGISTER_VERIFY_ISSUER: Lazy<String> = Lazy::new(|| format!("{}|regis
 
 static PRIVATE_RSA_KEY: OnceCell<EncodingKey> = OnceCell::new();
 static PUBLIC_RSA_KEY: OnceCell<DecodingKey> = OnceCell::new();
+static SSO_TOKEN_KEY: OnceCell<[u8; 32]> = OnceCell::new();
 
 pub fn initialize_keys() -> Result<(), Error> {
     fn read_key(create_if_missing: bool) -> Result<(Rsa<openssl::pkey::Private>, Vec<u8>), Error> {
@@ -89,6 +92,15 @@ pub fn initialize_keys() -> Result<(), Error> {
     if PUBLIC_RSA_KEY.set(dec).is_err() {
         err!("PUBLIC_RSA_KEY must only be initialized once")
     }
+
+    // Key used to encrypt the SSO provider tokens wrapped in our refresh token
+    let sso_token_secret = match CONFIG.sso_token_encryption_key() {
+        Some(key) => key.into_bytes(),
+        None => priv_key_buffer,
+    };
+    if SSO_TOKEN_KEY.set(crypto::derive_aes_key(&sso_token_secret, b"vaultwarden-sso-token")).is_err() {
+        err!("SSO_TOKEN_KEY must only be initialized once")
+    }
     Ok(())
 }
 
@@ -1108,6 +1120,47 @@ pub enum TokenWrapper {
     Refresh(String),
 }
 
+// Version prefix of the encrypted SSO tokens, allow to change the format or key later.
+const SSO_TOKEN_V1: &str = "v1.";
+
+// The SSO provider tokens are wrapped in our refresh token, encrypt them so they are not readable by the client.
+pub fn encrypt_sso_token(token: &str) -> String {
+    let encrypted = crypto::aes_gcm_encrypt(SSO_TOKEN_KEY.wait(), token.as_bytes());
+    format!("{SSO_TOKEN_V1}{}", BASE64URL_NOPAD.encode(&encrypted))
+}
+
+// Stable opaque id of a provider identity, it changes with `SSO_TOKEN_ENCRYPTION_KEY` (or the RSA key).
+pub fn sso_external_id(identifier: &str) -> String {
+    let key = crypto::derive_aes_key(SSO_TOKEN_KEY.wait(), b"vaultwarden-sso-external-id");
+    crypto::hmac_sha256_sign_bytes(&key, identifier)
+}
+
+// Pseudonymized value for the SSO audit log with `SSO_AUDIT_HASH_PII`, stable across restarts like `sso_external_id`.
+pub fn sso_audit_hash(value: &str) -> String {
+    let key = crypto::derive_aes_key(SSO_TOKEN_KEY.wait(), b"vaultwarden-sso-audit");
+    crypto::hmac_sha256_sign_bytes(&key, value)
+}
+
+// Token without version prefix were issued before the encryption and are used as is.
+// They will be replaced by an encrypted one on the next refresh.
+pub fn decrypt_sso_token(token: &str) -> ApiResult<String> {
+    match token.strip_prefix(SSO_TOKEN_V1) {
+        None => Ok(token.to_string()),
+        Some(encoded) => {
+            let decrypted = BASE64URL_NOPAD
+                .decode(encoded.as_bytes())
+                .ok()
+                .and_then(|encrypted| crypto::aes_gcm_decrypt(SSO_TOKEN_KEY.wait(), &encrypted))
+                .and_then(|decrypted| String::from_utf8(decrypted).ok());
+
+            match decrypted {
+                None => err_silent!("Failed to decrypt SSO token, login again"),
+                Some(token) => Ok(token),
+            }
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RefreshJwtClaims {
     // Not before
@@ -1122,6 +1175,10 @@ pub struct RefreshJwtClaims {
     pub device_token: String,
 
     pub token: Option<TokenWrapper>,
+
+    // Encrypted SSO id_token used as logout hint
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -1166,6 +1223,7 @@ impl AuthTokens {
             sub,
             device_token: device.refresh_token.clone(),
             token: None,
+            id_token: None,
         };
 
         Self {
@@ -1203,12 +1261,24 @@ pub async fn refresh_tokens(
         Some(user) => user,
     };
 
+    if refresh_claims.sub == AuthMethod::Sso && CONFIG.sso_enabled() {
+        sso::check_session_expiration(&device)?;
+    }
+
     let auth_tokens = match refresh_claims.sub {
         AuthMethod::Sso if CONFIG.sso_enabled() && CONFIG.sso_auth_only_not_session() => {
-            AuthTokens::new(&device, &user, refresh_claims.sub, client_id)
+            let mut auth_tokens = AuthTokens::new(&device, &user, refresh_claims.sub, client_id);
+            auth_tokens.refresh_claims.id_token = refresh_claims.id_token;
+            // The provider session is not refreshed, keep the expiration of the login
+            let provider_exp = device.sso_expires_at.map(|exp| exp.and_utc().timestamp());
+            sso::cap_session(&mut device, &mut auth_tokens, provider_exp);
+            auth_tokens
         }
         AuthMethod::Sso if CONFIG.sso_enabled() => {
-            sso::exchange_refresh_token(&device, &user, client_id, refresh_claims).await?
+            let mut auth_tokens = sso::exchange_refresh_token(&device, &user, client_id, refresh_claims).await?;
+            sso::cap_session(&mut device, &mut auth_tokens, None);
+            device.sso_token = sso::device_token(&auth_tokens);
+            auth_tokens
         }
         AuthMethod::Sso => err!("SSO is now disabled, Login again using email and master password"),
         AuthMethod::Password if CONFIG.sso_enabled() && CONFIG.sso_only() => err!("SSO is now required, Login again"),
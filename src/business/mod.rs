@@ -1 +1,2 @@
 pub mod organization_logic;
+pub mod user_logic;
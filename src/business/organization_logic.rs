@@ -24,7 +24,7 @@ pub async fn invite(
     let mut membership_status = MembershipStatus::Invited;
 
     // automatically accept existing users if mail is disabled or config if set
-    if (!user.password_hash.is_empty() && !CONFIG.mail_enabled())
+    if (user.is_registered() && !CONFIG.mail_enabled())
         || (CONFIG.sso_enabled() && CONFIG.organization_invite_auto_accept())
     {
         membership_status = MembershipStatus::Accepted;
@@ -155,6 +155,42 @@ pub async fn admin_check(
     }
 }
 
+// Accept the pending invitations of a user logged in with SSO, the email was verified by the provider.
+// Memberships which would violate an organization policy, or which require an account recovery enrollment
+// (the reset password key can only be provided by the client), are kept as invited.
+pub async fn accept_sso_invites(user: &User, conn: &mut DbConn) -> EmptyResult {
+    Invitation::take(&user.email, conn).await;
+
+    for mut member in Membership::find_invited_by_user(&user.uuid, conn).await {
+        if OrgPolicy::org_is_reset_password_auto_enroll(&member.org_uuid, conn).await {
+            info!("Not accepting invitation {}, the organization requires account recovery enrollment", member.uuid);
+            continue;
+        }
+
+        if member.atype < MembershipType::Admin {
+            if let Err(err) = admin_check(&member, "accept the invitation", false, conn).await {
+                warn!("Not accepting invitation for user {}: {}", user.uuid, err.message());
+                continue;
+            }
+        }
+
+        member.status = MembershipStatus::Accepted as i32;
+        member.save(conn).await?;
+        info!("Accepted invitation {} of user {} on SSO login", member.uuid, user.uuid);
+
+        if CONFIG.mail_enabled() {
+            if let Some(org) = Organization::find_by_uuid(&member.org_uuid, conn).await {
+                let invited_by = member.invited_by_email.unwrap_or(org.billing_email);
+                if let Err(err) = mail::send_invite_accepted(&user.email, &invited_by, &org.name).await {
+                    error!("Failed to send the invitation accepted mail of {} to {invited_by}: {err}", member.uuid);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
 pub async fn restore_member(
     act_user_id: &UserId,
     device: &Device,
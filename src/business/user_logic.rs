@@ -0,0 +1,52 @@
+use crate::{
+    api::{core::log_event, EmptyResult, Notify},
+    db::models::*,
+    db::DbConn,
+};
+
+// Disable the user and revoke all its sessions
+pub async fn disable_user(user: &mut User, nt: &Notify<'_>, conn: &mut DbConn) -> EmptyResult {
+    crate::sso::revoke_user_tokens(&user.uuid, conn).await;
+    Device::delete_all_by_user(&user.uuid, conn).await?;
+    user.reset_security_stamp();
+    user.enabled = false;
+
+    let save_result = user.save(conn).await;
+
+    nt.send_logout(user, None, conn).await;
+
+    save_result
+}
+
+// Remove the SSO association, the user will have to use its master password (or associate again with SSO).
+// All the sessions are revoked since their refresh token wrap the provider tokens of the removed identity,
+// the stored provider tokens are revoked at the provider.
+pub async fn unlink_sso(
+    user: &mut User,
+    act_user_id: &UserId,
+    device_type: i32,
+    ip: &std::net::IpAddr,
+    conn: &mut DbConn,
+) -> EmptyResult {
+    SsoUser::delete(&user.uuid, conn).await?;
+
+    crate::sso::revoke_user_tokens(&user.uuid, conn).await;
+    Device::unlink_sso_by_user(&user.uuid, conn).await?;
+    user.reset_security_stamp();
+    user.save(conn).await?;
+
+    for membership in Membership::find_any_state_by_user(&user.uuid, conn).await {
+        log_event(
+            EventType::OrganizationUserUnlinkedSso as i32,
+            &membership.uuid,
+            &membership.org_uuid,
+            act_user_id,
+            device_type,
+            ip,
+            conn,
+        )
+        .await;
+    }
+
+    Ok(())
+}
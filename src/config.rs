@@ -3,7 +3,7 @@ use std::{
     env::consts::EXE_SUFFIX,
     process::exit,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU64, Ordering},
         RwLock,
     },
 };
@@ -18,7 +18,10 @@ use crate::{
     db::models::OrganizationId,
     db::DbConnType,
     error::Error,
-    util::{get_env, get_env_bool, get_web_vault_version, is_valid_email, parse_experimental_client_feature_flags},
+    util::{
+        email_domain_to_ascii, get_env, get_env_bool, get_web_vault_version, is_valid_email,
+        parse_experimental_client_feature_flags,
+    },
 };
 
 static CONFIG_FILE: Lazy<String> = Lazy::new(|| {
@@ -46,7 +49,11 @@ macro_rules! make_config {
             $name:ident : $ty:ident, $editable:literal, $none_action:ident $(, $default:expr)?;
         )+},
     )+) => {
-        pub struct Config { inner: RwLock<Inner> }
+        pub struct Config {
+            inner: RwLock<Inner>,
+            // Incremented on each change of the configuration at runtime, to drop the values derived from it
+            generation: AtomicU64,
+        }
 
         struct Inner {
             rocket_shutdown_handle: Option<rocket::Shutdown>,
@@ -171,6 +178,12 @@ macro_rules! make_config {
                     config.http_request_block_regex = config.icon_blacklist_regex.clone();
                 }
 
+                // Multi-tenant issuers of the provider profile
+                if config.sso_issuer_trusted.is_none() {
+                    config.sso_issuer_trusted =
+                        sso_profile(&config.sso_provider_profile).and_then(|p| p.issuer_trusted(&config.sso_authority));
+                }
+
                 config
             }
         }
@@ -442,6 +455,9 @@ make_config! {
         /// Purge incomplete sso nonce. |> Cron schedule of the job that cleans leftover nonce in db due to incomplete sso login.
         /// Defaults to daily. Set blank to disable this job.
         purge_incomplete_sso_nonce: String, false,  def,   "0 20 0 * * *".to_string();
+        /// Flag stale SSO memberships |> Cron schedule of the job that flags the memberships not seen on a SSO login for `SSO_ORGANIZATIONS_STALE_DAYS`.
+        /// Defaults to daily. Set blank to disable this job.
+        sso_organizations_stale_schedule: String, false, def, "0 40 0 * * *".to_string();
     },
 
     /// General settings
@@ -530,6 +546,9 @@ make_config! {
         /// Admin token/Argon2 PHC |> The plain text token or Argon2 PHC string used to authenticate in this very same page. Changing it here will not deauthorize the current session!
         admin_token:            Pass,   true,   option;
 
+        /// Metrics token |> Bearer token accepted on `/admin/metrics` for a Prometheus scraper, the admin session is still accepted. Disabled when not set
+        metrics_token:          Pass,   true,   option;
+
         /// Invitation organization name |> Name shown in the invitation emails that don't come from a specific organization
         invitation_org_name:    String, true,   def,    "Vaultwarden".to_string();
 
@@ -671,40 +690,105 @@ make_config! {
         sso_only:                       bool,   true,   def,    false;
         /// Allow email association |> Associate existing non-sso user based on email
         sso_signups_match_email:        bool,   true,   def,    true;
+        /// Confirm email association |> Send an email to the existing user to confirm the association before the first SSO login (requires SMTP). Disable only if you trust the provider email verification
+        sso_link_confirmation:          bool,   true,   def,    true;
+        /// Account linking policy |> When the SSO email matches an existing account with a master password: `auto`, `require_confirmation` or `disallow`. Default derived from the two previous settings
+        sso_account_linking:            String, true,   auto,   |c| if !c.sso_signups_match_email {
+            "disallow"
+        } else if c.sso_link_confirmation {
+            "require_confirmation"
+        } else {
+            "auto"
+        }.to_string();
         /// Allow unknown email verification status |> Allowing this with `SSO_SIGNUPS_MATCH_EMAIL=true` open potential account takeover.
         sso_allow_unknown_email_verification: bool, false, def, false;
+        /// Auto accept invitations |> Accept the pending organization invitations on SSO login when the provider verified the email. Memberships still need to be confirmed by an admin.
+        sso_auto_accept_invites:        bool,   true,   def,    false;
         /// Client ID
-        sso_client_id:                  String, false,   def,    String::new();
+        sso_client_id:                  String, true,    def,    String::new();
         /// Client Key
-        sso_client_secret:              Pass,   false,   def,    String::new();
+        sso_client_secret:              Pass,   true,    def,    String::new();
         /// Authority Server |> Base url of the OIDC provider discovery endpoint (without `/.well-known/openid-configuration`)
-        sso_authority:                  String, false,   def,    String::new();
+        sso_authority:                  String, true,    def,    String::new();
+        /// Discovery url |> Full url of the discovery document when the provider does not serve it at `SSO_AUTHORITY/.well-known/openid-configuration`
+        sso_discovery_url:              String, true,   option;
+        /// Provider profile |> Preset of scopes, token paths and issuer handling: `keycloak`, `azure`, `google`, `authentik` or `okta`. Each value can still be overridden
+        sso_provider_profile:           String, false,  option;
         /// Authorization request scopes |> List the of the needed scope (`openid` is implicit)
-        sso_scopes:                     String, false,  def,   "email profile".to_string();
+        sso_scopes:                     String, true,   auto,   |c| sso_profile(&c.sso_provider_profile).map_or("email profile", |p| p.scopes()).to_string();
         /// Authorization request extra parameters
-        sso_authorize_extra_params:     String, false,  def,    String::new();
+        sso_authorize_extra_params:     String, true,   auto,   |c| sso_profile(&c.sso_provider_profile).map_or("", |p| p.authorize_extra_params()).to_string();
+        /// Requested claims |> JSON `claims` request parameter (ex: `{"id_token":{"email":{"essential":true}}}`) to ask the provider for specific claims in the id_token or userinfo
+        sso_request_claims:             String, true,   option;
+        /// Hosted domain |> Google Workspace domain, sent as `hd` in the authorization request and required in the `hd` claim of the id_token
+        sso_hosted_domain:              String, false,  option;
+        /// Provider MFA `amr` values |> Comma separated list of `amr` values (ex: `mfa,hwk`), one of them in the id_token satisfies the Vaultwarden 2FA
+        sso_mfa_amr_values:             String, false,  def,    String::new();
+        /// Provider MFA `acr` values |> Comma separated list of `acr` values, an id_token with one of them satisfies the Vaultwarden 2FA
+        sso_mfa_acr_values:             String, false,  def,    String::new();
         /// Use PKCE during Authorization flow
-        sso_pkce:                       bool,   false,   def,    true;
+        sso_pkce:                       bool,   true,    def,    true;
+        /// Allow insecure endpoints |> Development only: accept discovered token, userinfo, JWKS, introspection and revocation endpoints using plain http
+        sso_allow_insecure_endpoints:   bool,   true,   def,    false;
+        /// Authorization response mode |> `query` (default) or `form_post` to receive the provider code in a POST body instead of the callback url. The redirect to the client still carries the wrapped code
+        sso_response_mode:              String, false,  def,    "query".to_string();
         /// Regex for additionnal trusted Id token audience |> By default only the client_id is trsuted.
-        sso_audience_trusted:           String, false,  option;
+        sso_audience_trusted:           String, true,   option;
+        /// Regex for additionnal trusted issuer |> By default the issuer must be identical to the Authority Server. Relaxing this weakens the token validation, use an anchored regex.
+        sso_issuer_trusted:             String, true,   option;
+        /// Id token decryption keys |> Comma separated list of PEM private key files (RSA-OAEP or ECDH-ES) used to decrypt JWE id_tokens, tried in order.
+        sso_id_token_decryption_keys:   String, false,  option;
+        /// Allowed signing algorithms |> Comma separated list of the JWS algorithms accepted for the id_token (`none` is never allowed)
+        sso_allowed_signing_algs:       String, true,   def,    "RS256,ES256".to_string();
+        /// Require signed userinfo |> Request the userinfo as a signed JWT and reject plain JSON responses. Signed responses are always verified when the provider returns one.
+        sso_userinfo_signed:            bool,   true,   def,    false;
+        /// Userinfo access token delivery |> `header` (Bearer authorization header), `form` (POST body) or `query` (url parameter) for providers not supporting the header
+        sso_userinfo_token_delivery:    String, true,   def,    "header".to_string();
+        /// Token encryption key |> Secret used to encrypt the provider tokens wrapped in the session. Derived from the RSA private key if not set.
+        sso_token_encryption_key:       Pass,   false,  option;
+        /// Desktop and mobile redirect uris |> Comma separated list of deep links the desktop and mobile applications are allowed to use at the end of the flow. Loopback redirects can use a `{port}` placeholder.
+        sso_app_redirect_uris:          String, false,  def,    "bitwarden://sso-callback".to_string();
+        /// Sector redirect uris |> Comma separated callback urls of the other instances sharing the pairwise `sub` sector, served with this instance callback at `/identity/sso/sector-identifier`
+        sso_sector_redirect_uris:       String, true,   def,    String::new();
+        /// Subject migration |> Replace the stored identity of an SSO user when the provider returns a new `sub` with the same verified email (ex: switch to pairwise subjects). Only enable during the migration
+        sso_subject_migration:          bool,   true,   def,    false;
+        /// Extra redirect uris |> Comma separated list of additional redirect uris accepted for any client (exact match). Loopback redirects can use a `{port}` placeholder.
+        sso_extra_redirects:            String, false,  def,    String::new();
+        /// Allowed return hosts |> Comma separated list of hosts (`*.example.com` for subdomains) allowed as caller supplied return url such as the `post_logout_redirect_uri`. The `DOMAIN` is always allowed
+        sso_allowed_redirect_hosts:     String, true,   def,    String::new();
+        /// Nonce length |> Number of random bytes of the authorization request nonce (minimum 16), default to the openidconnect random nonce (16 bytes).
+        sso_nonce_bytes:                usize,  false,  option;
         /// CallBack Path |> Generated from Domain.
         sso_callback_path:              String, false,  generated, |c| generate_sso_callback_path(&c.domain);
         /// Optional sso master password policy |> Ex format: '{"enforceOnLogin":false,"minComplexity":3,"minLength":12,"requireLower":false,"requireNumbers":false,"requireSpecial":false,"requireUpper":false}'
         sso_master_password_policy:     String, true,  option;
         /// Use sso only for auth not the session lifecycle |> Use default Vaultwarden session lifecycle (Idle refresh token valid for 30days)
         sso_auth_only_not_session:      bool,   true,   def,    false;
+        /// Refresh token policy |> `required` refuses a login without provider refresh token, `optional` uses the access token lifetime when it's missing and `none` ignores any returned refresh token
+        sso_refresh_token_policy:       String, true,   def,    "optional".to_string();
+        /// Cap sessions at the provider expiry |> Refuse to refresh a session once the provider tokens of its SSO login or last refresh expired, also limit the sessions with `SSO_AUTH_ONLY_NOT_SESSION`
+        sso_session_cap_idp_expiry:     bool,   true,   def,    false;
         /// Roles mapping |> Enable the mapping of roles (user/admin) from the access_token
         sso_roles_enabled:              bool,   false,   def,    false;
         /// Missing/Invalid roles default to user
         sso_roles_default_to_user:      bool,   false,   def,    true;
+        /// Roles and groups from the access token |> Read `sso_roles_token_path` and `sso_organizations_token_path` in the access token claims when it's a JWT instead of the id_token (ex: Keycloak mappers only added to the access token)
+        sso_claims_from_access_token:   bool,   false,  def,    false;
         /// Id token path to read roles
-        sso_roles_token_path:           String, false,  auto,   |c| format!("/resource_access/{}/roles", c.sso_client_id);
+        sso_roles_token_path:           String, false,  auto,   |c| match sso_profile(&c.sso_provider_profile) {
+            Some(profile) => profile.roles_token_path(&c.sso_client_id),
+            None => format!("/resource_access/{}/roles", c.sso_client_id),
+        };
         /// Invite users to Organizations |> Deprecated, More details [README.md](https://github.com/timshel/vaultwarden/blob/1.34.1-1/README.md#deprecation)
         sso_organizations_invite:       bool,   false,   def,    false;
         /// Organizations mapping |> Enable the mapping of organization, membership role and groups.
         sso_organizations_enabled:      bool,   false,   def,    false;
         /// Process revocation
         sso_organizations_revocation:   bool,   false,   def,    false;
+        /// Revocation dry run |> Only log the memberships which would be revoked by the login sync or flagged by the stale memberships job
+        sso_organizations_revocation_dry_run: bool, true, def,  false;
+        /// Stale membership delay |> Flag the synced memberships whose organization was not in the provider groups on a login for this number of days
+        sso_organizations_stale_days:   i64,    true,   option;
         /// Id token path to read Organization/Groups
         sso_organizations_token_path:   String, false,   def,    "/groups".to_string();
         /// Organization Id mapping |> Deprecated. More details [README.md](https://github.com/timshel/vaultwarden/blob/1.34.1-1/README.md#deprecation)
@@ -713,8 +797,66 @@ make_config! {
         sso_organizations_groups_enabled: bool, false, def, false;
         /// Grant acceess to all collections
         sso_organizations_all_collections: bool, true,  def,   true;
+        /// Default organization |> Id of the organization new SSO users are added to, the membership still need to be confirmed by an admin
+        sso_default_org_id:             String, true,   option;
+        /// Default organization role |> Role of the new users in the default organization: `User`, `Manager`, `Admin` or `Owner`
+        sso_default_org_role:           String, true,   def,    "User".to_string();
+        /// Default organization collections |> Comma separated list of collection ids of the default organization the new `User` members can access
+        sso_default_collections:        String, true,   option;
+        /// Key Connector urls |> Semicolon separated list of `<organization id>:<url>`, the members of those organizations logging in with SSO store their master key in the Key Connector instead of using a master password
+        sso_key_connector_urls:         String, true,   def,    String::new();
+        /// Trusted device encryption |> Comma separated list of organization ids whose SSO members approve their new devices (from another device or by an admin) instead of using a master password
+        sso_trusted_device_orgs:        String, true,   def,    String::new();
         /// Client cache for discovery endpoint. |> Duration in seconds (0 or less to disable). More details: https://github.com/dani-garcia/vaultwarden/blob/sso-support/SSO.md#client-cache
         sso_client_cache_expiration:    u64,    true,   def,    0;
+        /// Provider request attempts |> Number of attempts of the discovery, token and userinfo requests when they fail with a transient error (connection error, timeout or 5xx), `1` to disable the retry
+        sso_retry_attempts:             u32,    true,   def,    3;
+        /// Provider retry delay |> Delay in milliseconds before the first retry, doubled on each following attempt
+        sso_retry_base_delay_ms:        u64,    true,   def,    200;
+        /// Clock leeway |> Tolerance in seconds applied to all the time checks of the SSO flow (id_token `exp`, `iat`, `nbf` and `auth_time`, provider tokens `exp` and `nbf`, step-up `auth_time` and the nonce lifetime)
+        sso_clock_leeway:               u64,    true,   def,    60;
+        /// [Deprecated] Pending authentication store |> Use `sso_state_backend` instead, `db` is the same as `SSO_STATE_BACKEND=db`
+        sso_auth_store:                 String, false,  option;
+        /// State backend |> Where to keep the in-flight flows (nonces and pending authentications): `default` (nonces in the database, authentications in memory), `db` (both in the database, survives restarts and is shared between instances), `memory` (local to the instance, lost on restart) or `redis` (shared by all the instances, requires Redis 6.2+)
+        sso_state_backend:              String, false,  auto,   |c| if c.sso_auth_store.as_deref() == Some("db") { "db" } else { "default" }.to_string();
+        /// Redis url |> Used with `SSO_STATE_BACKEND=redis`: `rediss://[[username]:password@]host[:port][/database]`, plain `redis://` is only accepted for a local server
+        sso_state_redis_url:            Pass,   false,  option;
+        /// Provision webhook url |> Url notified with a POST when a new user is created using SSO
+        sso_provision_webhook_url:      String, true,   option;
+        /// Provision webhook secret |> Secret used to sign the webhook payload (HMAC-SHA256 in the `X-Vaultwarden-Signature` header)
+        sso_provision_webhook_secret:   Pass,   true,   option;
+        /// SCIM token |> Bearer token of the SCIM 2.0 provisioning endpoint (`/scim/v2`), disabled when not set
+        sso_scim_token:                 Pass,   true,   option;
+        /// Distributed claims |> Resolve the claims referenced by `_claim_names`/`_claim_sources` (one request per distributed source)
+        sso_distributed_claims:         bool,   false,  def,    false;
+        /// Captured claims max size |> Maximum size in bytes of the merged id_token and userinfo claims kept for the login (`0` to not keep them), the largest claims are dropped first
+        sso_claims_max_size:            usize,  false,  def,    16384;
+        /// Redacted claims |> Comma separated list of the claims never kept with the merged id_token and userinfo claims
+        sso_claims_redacted:            String, false,  def,    "at_hash,c_hash,nonce,_claim_names,_claim_sources".to_string();
+        /// Email claim path |> Path to read the email in the id_token or userinfo claims (ex: `["https://app/email"]` or `profile.email`), default to the standard `email` claim
+        sso_email_claim:                String, false,  option;
+        /// Email aliases claim path |> Path to a list of verified email aliases (ex: `emails`), used to match an existing account when the primary email does not
+        sso_email_aliases_claim:        String, false,  option;
+        /// Account status claim path |> Path to the account status in the id_token or userinfo claims (ex: `account_status`), the login is refused when the claim is missing or not one of the active values
+        sso_account_status_claim:       String, false,  option;
+        /// Account active values |> Comma separated list of the `sso_account_status_claim` values of an active account (case-insensitive, booleans and numbers are compared as text)
+        sso_account_active_values:      String, false,  def,    "active".to_string();
+        /// Blocked subjects |> Comma separated list of `sub` claims which will be refused even with a valid token (exact match)
+        sso_blocked_subs:               String, true,   def,    String::new();
+        /// Blocked emails |> Comma separated list of emails which will be refused even with a valid token (case-insensitive)
+        sso_blocked_emails:             String, true,   def,    String::new();
+        /// Seconds between authorize requests |> Number of seconds, on average, between SSO authorize requests from the same IP address before rate limiting kicks in
+        sso_ratelimit_seconds:          u64,    false,  def,    6;
+        /// Max burst size for authorize requests |> Allow a burst of requests of up to this size, while maintaining the average indicated by `sso_ratelimit_seconds`
+        sso_ratelimit_max_burst:        u32,    false,  def,    10;
+        /// Seconds between failed SSO logins |> Number of seconds, on average, between failed SSO authorize or code exchanges from the same IP address before it is blocked
+        sso_failure_ratelimit_seconds:  u64,    false,  def,    60;
+        /// Max burst size for failed SSO logins |> Allow a burst of failures of up to this size, while maintaining the average indicated by `sso_failure_ratelimit_seconds`
+        sso_failure_ratelimit_max_burst: u32,   false,  def,    5;
+        /// Audit log |> Log a JSON entry (target `sso_audit`) for each SSO login success or failure with the subject, email, provider and failure reason
+        sso_audit_log:                  bool,   true,   def,    false;
+        /// Hash audit PII |> Replace the subject and email of the audit entries with a keyed HMAC-SHA256, stable for a given `SSO_TOKEN_ENCRYPTION_KEY`
+        sso_audit_hash_pii:             bool,   true,   def,    false;
         /// Log all tokens |> `LOG_LEVEL=debug` or `LOG_LEVEL=info,vaultwarden::sso=debug` is required
         sso_debug_tokens:               bool,   true,   def,    false;
         /// Force fail auth code exchange |> Allow to log and return the code used in `authorization_code` flow without consuming it (SSO login will become impossilbe).
@@ -870,6 +1012,10 @@ fn validate_config(cfg: &ConfigItems) -> Result<(), Error> {
         }
     }
 
+    if cfg.metrics_token.as_ref().is_some_and(|token| token.trim().is_empty()) {
+        err!("`METRICS_TOKEN` cannot be empty, unset it to disable the bearer token")
+    }
+
     if cfg.push_enabled && (cfg.push_installation_id == String::new() || cfg.push_installation_key == String::new()) {
         err!(
             "Misconfigured Push Notification service\n\
@@ -957,14 +1103,192 @@ fn validate_config(cfg: &ConfigItems) -> Result<(), Error> {
     }
 
     if cfg.sso_enabled {
-        if cfg.sso_client_id.is_empty() || cfg.sso_client_secret.is_empty() || cfg.sso_authority.is_empty() {
+        if cfg.sso_client_id.is_empty() || cfg.sso_authority.is_empty() {
             err!("`SSO_CLIENT_ID`, `SSO_CLIENT_SECRET` and `SSO_AUTHORITY` must be set for SSO support")
         }
+        internal_sso_client_secret(&cfg.sso_client_secret)?;
 
         internal_sso_issuer_url(&cfg.sso_authority)?;
+
+        if let Some(ref url) = cfg.sso_discovery_url {
+            match Url::parse(url) {
+                Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => (),
+                _ => err!(format!("Invalid `SSO_DISCOVERY_URL` ({url}), expected an http(s) url")),
+            }
+        }
         internal_sso_redirect_url(&cfg.sso_callback_path)?;
         check_master_password_policy(&cfg.sso_master_password_policy)?;
-        internal_sso_authorize_extra_params_vec(&cfg.sso_authorize_extra_params)?;
+        let extra_params = internal_sso_authorize_extra_params_vec(&cfg.sso_authorize_extra_params)?;
+        if !["header", "form", "query"].contains(&cfg.sso_userinfo_token_delivery.as_str()) {
+            err!(format!(
+                "Invalid `SSO_USERINFO_TOKEN_DELIVERY` ({}), expected `header`, `form` or `query`",
+                cfg.sso_userinfo_token_delivery
+            ))
+        }
+
+        match cfg.sso_response_mode.as_str() {
+            "query" => (),
+            "form_post" if extra_params.iter().any(|(name, _)| name == "response_mode") => {
+                err!("`SSO_RESPONSE_MODE` cannot be combined with a `response_mode` in `SSO_AUTHORIZE_EXTRA_PARAMS`")
+            }
+            "form_post" => (),
+            mode => err!(format!("Invalid `SSO_RESPONSE_MODE` ({mode}), expected `query` or `form_post`")),
+        }
+        if let Some(ref claims) = cfg.sso_request_claims {
+            internal_sso_request_claims(claims)?;
+            if extra_params.iter().any(|(name, _)| name == "claims") {
+                err!("`SSO_AUTHORIZE_EXTRA_PARAMS` can't contain `claims` when `SSO_REQUEST_CLAIMS` is set")
+            }
+        }
+        if cfg.sso_hosted_domain.is_some() && extra_params.iter().any(|(name, _)| name == "hd") {
+            err!("`SSO_AUTHORIZE_EXTRA_PARAMS` can't contain `hd` when `SSO_HOSTED_DOMAIN` is set")
+        }
+
+        for uri in cfg.sso_sector_redirect_uris.split(',').map(str::trim).filter(|uri| !uri.is_empty()) {
+            if !Url::parse(uri).is_ok_and(|url| url.scheme() == "https") {
+                err!(format!("`SSO_SECTOR_REDIRECT_URIS` contains an invalid uri ({uri}), expected an https url"))
+            }
+        }
+
+        for (name, uris) in
+            [("SSO_APP_REDIRECT_URIS", &cfg.sso_app_redirect_uris), ("SSO_EXTRA_REDIRECTS", &cfg.sso_extra_redirects)]
+        {
+            for uri in uris.split(',').map(str::trim).filter(|uri| !uri.is_empty()) {
+                if !uri.contains("{port}") {
+                    if let Err(err) = Url::parse(uri) {
+                        err!(format!("`{name}` contains an invalid uri ({uri}): {err}"))
+                    }
+                    continue;
+                }
+
+                let loopback = ["http://127.0.0.1:{port}", "http://[::1]:{port}", "http://localhost:{port}"];
+                let path = uri.split_once("{port}").map(|(_, path)| path).unwrap_or_default();
+                if !loopback.iter().any(|prefix| uri.starts_with(prefix))
+                    || !(path.is_empty() || path.starts_with('/'))
+                    || path.contains("{port}")
+                {
+                    err!(format!("`{name}` `{{port}}` placeholder is only allowed for loopback redirect ({uri})"))
+                }
+            }
+        }
+
+        if !(1..=10).contains(&cfg.sso_retry_attempts) {
+            err!("`SSO_RETRY_ATTEMPTS` must be between 1 and 10")
+        }
+
+        if let Some(len) = cfg.sso_nonce_bytes {
+            if !(16..=256).contains(&len) {
+                err!("`SSO_NONCE_BYTES` must be between 16 and 256")
+            }
+        }
+
+        match (cfg.sso_auth_store.as_deref(), cfg.sso_state_backend.as_str()) {
+            (None | Some("memory"), _) => (),
+            (Some("db"), "default") => {
+                err!("The deprecated `SSO_AUTH_STORE=db` conflicts with `SSO_STATE_BACKEND=default`, use `SSO_STATE_BACKEND=db`")
+            }
+            (Some("db"), _) => (),
+            (Some(store), _) => err!(format!("Invalid SSO_AUTH_STORE ({store}), expected `memory` or `db`")),
+        }
+
+        match (cfg.sso_state_backend.as_str(), &cfg.sso_state_redis_url) {
+            ("default" | "db" | "memory", _) => (),
+            ("redis", None) => err!("`SSO_STATE_REDIS_URL` is required with `SSO_STATE_BACKEND=redis`"),
+            ("redis", Some(url)) => {
+                if let Err(err) = crate::redis_client::RedisClient::from_url(url) {
+                    err!(format!("Invalid `SSO_STATE_REDIS_URL`: {err}"))
+                }
+            }
+            (backend, _) => {
+                err!(format!("Invalid SSO_STATE_BACKEND ({backend}), expected `default`, `db`, `memory` or `redis`"))
+            }
+        }
+
+        if let Some(ref url) = cfg.sso_provision_webhook_url {
+            if Url::parse(url).is_err() {
+                err!(format!("Invalid `SSO_PROVISION_WEBHOOK_URL`: {url}"))
+            }
+            if cfg.sso_provision_webhook_secret.as_ref().is_none_or(String::is_empty) {
+                err!("`SSO_PROVISION_WEBHOOK_SECRET` is required with `SSO_PROVISION_WEBHOOK_URL`")
+            }
+        }
+
+        if let Some(ref path) = cfg.sso_email_claim {
+            if let Err(err) = crate::sso_claims::validate_claim_path(path) {
+                err!(format!("Invalid `SSO_EMAIL_CLAIM`: {err}"))
+            }
+        }
+
+        if let Some(ref profile) = cfg.sso_provider_profile {
+            if crate::sso::ProviderProfile::parse(profile).is_none() {
+                err!(format!(
+                    "Invalid `SSO_PROVIDER_PROFILE` ({profile}), expected `keycloak`, `azure`, `google`, `authentik` or `okta`"
+                ))
+            }
+        }
+
+        if cfg.sso_scim_token.as_ref().is_some_and(|token| token.len() < 32) {
+            err!("`SSO_SCIM_TOKEN` must be at least 32 characters long")
+        }
+
+        if crate::sso::AccountLinking::parse(&cfg.sso_account_linking).is_none() {
+            err!(format!(
+                "Invalid `SSO_ACCOUNT_LINKING` ({}), expected `auto`, `require_confirmation` or `disallow`",
+                cfg.sso_account_linking
+            ))
+        }
+
+        if cfg.sso_clock_leeway > 300 {
+            err!("`SSO_CLOCK_LEEWAY` cannot be more than 300 seconds")
+        }
+
+        if crate::sso::RefreshTokenPolicy::parse(&cfg.sso_refresh_token_policy).is_none() {
+            err!(format!(
+                "Invalid `SSO_REFRESH_TOKEN_POLICY` ({}), expected `required`, `optional` or `none`",
+                cfg.sso_refresh_token_policy
+            ))
+        }
+
+        if let Some(ref path) = cfg.sso_email_aliases_claim {
+            if let Err(err) = crate::sso_claims::validate_claim_path(path) {
+                err!(format!("Invalid `SSO_EMAIL_ALIASES_CLAIM`: {err}"))
+            }
+        }
+
+        if let Some(ref path) = cfg.sso_account_status_claim {
+            if let Err(err) = crate::sso_claims::validate_claim_path(path) {
+                err!(format!("Invalid `SSO_ACCOUNT_STATUS_CLAIM`: {err}"))
+            }
+            if cfg.sso_account_active_values.split(',').all(|value| value.trim().is_empty()) {
+                err!("`SSO_ACCOUNT_ACTIVE_VALUES` cannot be empty with `SSO_ACCOUNT_STATUS_CLAIM`")
+            }
+        }
+
+        if let Some(ref regex_str) = cfg.sso_issuer_trusted {
+            if let Err(err) = regex::Regex::new(regex_str) {
+                err!(format!("Invalid SSO_ISSUER_TRUSTED regex ({regex_str}): {err}"))
+            }
+        }
+
+        let algs = internal_sso_allowed_signing_algs_vec(&cfg.sso_allowed_signing_algs);
+        if algs.is_empty() {
+            err!("`SSO_ALLOWED_SIGNING_ALGS` cannot be empty")
+        }
+        for alg in algs {
+            match serde_json::from_value(serde_json::Value::String(alg.clone())) {
+                Ok(openidconnect::core::CoreJwsSigningAlgorithm::None) => {
+                    err!("`none` is not allowed in `SSO_ALLOWED_SIGNING_ALGS`")
+                }
+                Ok(_) => (),
+                Err(_) => err!(format!("Unsupported signing algorithm `{alg}` in `SSO_ALLOWED_SIGNING_ALGS`")),
+            }
+        }
+
+        for path in internal_sso_id_token_decryption_keys_vec(&cfg.sso_id_token_decryption_keys) {
+            if std::fs::File::open(&path).is_err() {
+                err!(format!("Unable to read SSO_ID_TOKEN_DECRYPTION_KEYS file ({path})"));
+            }
+        }
 
         if cfg.sso_organizations_invite && !cfg.sso_organizations_enabled {
             warn!("SSO_ORGANIZATIONS_INVITE is DEPRECATED, replaced by SSO_ORGANIZATIONS_ENABLED");
@@ -977,6 +1301,29 @@ fn validate_config(cfg: &ConfigItems) -> Result<(), Error> {
         if cfg.org_groups_enabled && !cfg.sso_organizations_groups_enabled {
             warn!("SSO_ORGANIZATIONS_GROUPS_ENABLED is DEPRECATED, More details: https://github.com/timshel/vaultwarden/blob/1.34.1-1/README.md#deprecation");
         }
+
+        if cfg.sso_organizations_stale_days.is_some_and(|days| days < 1) {
+            err!("`SSO_ORGANIZATIONS_STALE_DAYS` must be at least 1")
+        }
+
+        if crate::db::models::MembershipType::from_str(&cfg.sso_default_org_role).is_none() {
+            err!(format!(
+                "Invalid `SSO_DEFAULT_ORG_ROLE` ({}), expected one of `User`, `Manager`, `Admin` or `Owner`",
+                cfg.sso_default_org_role
+            ))
+        }
+
+        if cfg.sso_default_collections.is_some() && cfg.sso_default_org_id.is_none() {
+            err!("`SSO_DEFAULT_COLLECTIONS` requires `SSO_DEFAULT_ORG_ID`")
+        }
+
+        internal_sso_key_connector_urls_map(&cfg.sso_key_connector_urls)?;
+
+        for org_id in internal_sso_trusted_device_orgs_vec(&cfg.sso_trusted_device_orgs) {
+            if Uuid::parse_str(&org_id).is_err() {
+                err!(format!("Invalid organization id in `SSO_TRUSTED_DEVICE_ORGS` ({org_id})"))
+            }
+        }
     }
 
     if cfg._enable_yubico {
@@ -1163,6 +1510,22 @@ fn internal_sso_issuer_url(sso_authority: &String) -> Result<openidconnect::Issu
     }
 }
 
+// A value prefixed with `base64:` is decoded.
+fn internal_sso_client_secret(secret: &str) -> Result<String, Error> {
+    let secret = match secret.trim().strip_prefix("base64:") {
+        Some(encoded) => match data_encoding::BASE64.decode(encoded.trim().as_bytes()).map(String::from_utf8) {
+            Ok(Ok(decoded)) => decoded.trim().to_string(),
+            _ => err!("`SSO_CLIENT_SECRET` has a `base64:` prefix but is not a valid base64 encoded string"),
+        },
+        None => secret.trim().to_string(),
+    };
+
+    if secret.is_empty() {
+        err!("`SSO_CLIENT_ID`, `SSO_CLIENT_SECRET` and `SSO_AUTHORITY` must be set for SSO support")
+    }
+    Ok(secret)
+}
+
 fn internal_sso_redirect_url(sso_callback_path: &String) -> Result<openidconnect::RedirectUrl, Error> {
     match openidconnect::RedirectUrl::new(sso_callback_path.clone()) {
         Err(err) => err!(format!("Invalid sso_callback_path ({sso_callback_path} built using `domain`) URL: {err}")),
@@ -1170,13 +1533,95 @@ fn internal_sso_redirect_url(sso_callback_path: &String) -> Result<openidconnect
     }
 }
 
+fn sso_profile(profile: &Option<String>) -> Option<crate::sso::ProviderProfile> {
+    profile.as_deref().and_then(crate::sso::ProviderProfile::parse)
+}
+
+// Parameters set by the flow itself, overriding them would break or weaken the authorization request
+const SSO_RESERVED_AUTHORIZE_PARAMS: [&str; 8] = [
+    "client_id",
+    "state",
+    "nonce",
+    "redirect_uri",
+    "scope",
+    "response_type",
+    "code_challenge",
+    "code_challenge_method",
+];
+
 fn internal_sso_authorize_extra_params_vec(config: &str) -> Result<Vec<(String, String)>, Error> {
-    match parse_param_list(config.to_owned(), '&', '=') {
+    let params = match parse_param_list(config.to_owned(), '&', '=') {
         Err(e) => err!(format!("Invalid SSO_AUTHORIZE_EXTRA_PARAMS: {e}")),
-        Ok(params) => Ok(params),
+        Ok(params) => params,
+    };
+
+    if let Some((name, _)) = params
+        .iter()
+        .find(|(name, _)| SSO_RESERVED_AUTHORIZE_PARAMS.iter().any(|r| r.eq_ignore_ascii_case(name.trim())))
+    {
+        err!(format!("Invalid SSO_AUTHORIZE_EXTRA_PARAMS: `{name}` is set by Vaultwarden and cannot be overridden"))
+    }
+
+    Ok(params)
+}
+
+// Only the `id_token` and `userinfo` members of the OIDC `claims` parameter are defined, both objects of claim requests.
+// Returned compact to keep the authorization url short.
+fn internal_sso_request_claims(config: &str) -> Result<String, Error> {
+    let claims = match serde_json::from_str::<serde_json::Value>(config) {
+        Ok(serde_json::Value::Object(claims)) => claims,
+        Ok(_) => err!("Invalid `SSO_REQUEST_CLAIMS`, expected a JSON object"),
+        Err(e) => err!(format!("Invalid `SSO_REQUEST_CLAIMS`: {e}")),
+    };
+
+    for (member, requests) in &claims {
+        if !["id_token", "userinfo"].contains(&member.as_str()) {
+            err!(format!("Invalid `SSO_REQUEST_CLAIMS`: unknown member `{member}`, expected `id_token` or `userinfo`"))
+        }
+        if !requests.is_object() {
+            err!(format!("Invalid `SSO_REQUEST_CLAIMS`: `{member}` must be an object of claim requests"))
+        }
+    }
+
+    Ok(serde_json::Value::Object(claims).to_string())
+}
+
+fn internal_sso_allowed_signing_algs_vec(config: &str) -> Vec<String> {
+    config.split(',').map(str::trim).filter(|alg| !alg.is_empty()).map(str::to_string).collect()
+}
+
+fn internal_sso_id_token_decryption_keys_vec(config: &Option<String>) -> Vec<String> {
+    match config {
+        None => Vec::new(),
+        Some(keys) => keys.split(',').map(str::trim).filter(|k| !k.is_empty()).map(str::to_string).collect(),
     }
 }
 
+fn internal_sso_trusted_device_orgs_vec(config: &str) -> Vec<String> {
+    config.split(',').map(str::trim).filter(|o| !o.is_empty()).map(str::to_string).collect()
+}
+
+fn internal_sso_key_connector_urls_map(config: &str) -> Result<HashMap<OrganizationId, String>, Error> {
+    let mut urls = HashMap::new();
+    for entry in config.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+        // Split on the first `:` only since the url contains one
+        let Some((org_id, url)) = entry.split_once(':') else {
+            err!(format!("Invalid `SSO_KEY_CONNECTOR_URLS` entry ({entry}), expected `<organization id>:<url>`"))
+        };
+        let (org_id, url) = (org_id.trim(), url.trim().trim_end_matches('/'));
+        if Uuid::parse_str(org_id).is_err() {
+            err!(format!("Invalid organization id in `SSO_KEY_CONNECTOR_URLS` ({org_id})"))
+        }
+        if !url.starts_with("https://") || Url::parse(url).is_err() {
+            err!(format!(
+                "Invalid Key Connector url in `SSO_KEY_CONNECTOR_URLS` ({url}), it must start with `https://`"
+            ))
+        }
+        urls.insert(org_id.to_string().into(), url.to_string());
+    }
+    Ok(urls)
+}
+
 fn check_master_password_policy(sso_master_password_policy: &Option<String>) -> Result<(), Error> {
     let policy = sso_master_password_policy.as_ref().map(|mpp| serde_json::from_str::<serde_json::Value>(mpp));
     if let Some(Err(error)) = policy {
@@ -1310,6 +1755,10 @@ impl Config {
         let mut _overrides = Vec::new();
         let builder = _env.merge(&_usr, true, &mut _overrides);
 
+        // Tests never use the environment data folder
+        #[cfg(test)]
+        let builder = builder.merge(&ConfigBuilder::test_values(), false, &mut Vec::new());
+
         // Fill any missing with defaults
         let config = builder.build();
         if !SKIP_CONFIG_VALIDATION.load(Ordering::Relaxed) {
@@ -1325,9 +1774,14 @@ impl Config {
                 _usr,
                 _overrides,
             }),
+            generation: AtomicU64::new(0),
         })
     }
 
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
     pub fn update_config(&self, other: ConfigBuilder, ignore_non_editable: bool) -> Result<(), Error> {
         // Remove default values
         //let builder = other.remove(&self.inner.read().unwrap()._env);
@@ -1358,6 +1812,7 @@ impl Config {
             writer._usr = builder;
             writer._overrides = overrides;
         }
+        self.generation.fetch_add(1, Ordering::AcqRel);
 
         //Save to file
         use std::{fs::File, io::Write};
@@ -1393,10 +1848,16 @@ impl Config {
             warn!("Failed to parse email address '{email}'");
             return false;
         }
-        let email_domain = e[0].to_lowercase();
+        // Internationalized domains are compared in their punycode form, whatever the form used on each side
+        let email_domain = e[0].trim().to_lowercase();
+        let email_domain = email_domain_to_ascii(&email_domain).unwrap_or(email_domain);
         let whitelist = self.signups_domains_whitelist();
 
-        whitelist.is_empty() || whitelist.split(',').any(|d| d.trim() == email_domain)
+        whitelist.is_empty()
+            || whitelist
+                .split(',')
+                .map(str::trim)
+                .any(|d| d == email_domain || email_domain_to_ascii(d).is_ok_and(|d| d == email_domain))
     }
 
     /// Tests whether signup is allowed for an email address, taking into
@@ -1437,6 +1898,7 @@ impl Config {
             writer._usr = usr;
             writer._overrides = Vec::new();
         }
+        self.generation.fetch_add(1, Ordering::AcqRel);
 
         Ok(())
     }
@@ -1510,6 +1972,10 @@ impl Config {
         internal_sso_issuer_url(&self.sso_authority())
     }
 
+    pub fn sso_client_secret_value(&self) -> Result<String, Error> {
+        internal_sso_client_secret(&self.sso_client_secret())
+    }
+
     pub fn sso_redirect_url(&self) -> Result<openidconnect::RedirectUrl, Error> {
         internal_sso_redirect_url(&self.sso_callback_path())
     }
@@ -1522,12 +1988,100 @@ impl Config {
         internal_sso_authorize_extra_params_vec(&self.sso_authorize_extra_params())
     }
 
+    pub fn sso_request_claims_json(&self) -> Result<Option<String>, Error> {
+        self.sso_request_claims().as_deref().map(internal_sso_request_claims).transpose()
+    }
+
+    pub fn sso_app_redirect_uris_vec(&self) -> Vec<String> {
+        self.sso_app_redirect_uris()
+            .split(',')
+            .map(str::trim)
+            .filter(|uri| !uri.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    pub fn sso_account_active_values_vec(&self) -> Vec<String> {
+        self.sso_account_active_values()
+            .split(',')
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    // Content of the `sector_identifier_uri` document, this instance callback first
+    pub fn sso_sector_redirect_uris_vec(&self) -> Vec<String> {
+        std::iter::once(self.sso_callback_path())
+            .chain(
+                self.sso_sector_redirect_uris()
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|uri| !uri.is_empty())
+                    .map(str::to_string),
+            )
+            .collect()
+    }
+
+    pub fn sso_extra_redirects_vec(&self) -> Vec<String> {
+        self.sso_extra_redirects().split(',').map(str::trim).filter(|uri| !uri.is_empty()).map(str::to_string).collect()
+    }
+
+    pub fn sso_allowed_redirect_hosts_vec(&self) -> Vec<String> {
+        self.sso_allowed_redirect_hosts()
+            .split(',')
+            .map(str::trim)
+            .filter(|host| !host.is_empty())
+            .map(str::to_lowercase)
+            .collect()
+    }
+
+    pub fn sso_mfa_amr_values_vec(&self) -> Vec<String> {
+        self.sso_mfa_amr_values().split(',').map(str::trim).filter(|v| !v.is_empty()).map(str::to_string).collect()
+    }
+
+    pub fn sso_mfa_acr_values_vec(&self) -> Vec<String> {
+        self.sso_mfa_acr_values().split(',').map(str::trim).filter(|v| !v.is_empty()).map(str::to_string).collect()
+    }
+
+    pub fn sso_claims_redacted_vec(&self) -> Vec<String> {
+        self.sso_claims_redacted().split(',').map(str::trim).filter(|c| !c.is_empty()).map(str::to_string).collect()
+    }
+
+    pub fn sso_blocked_subs_vec(&self) -> Vec<String> {
+        self.sso_blocked_subs().split(',').map(str::trim).filter(|s| !s.is_empty()).map(str::to_string).collect()
+    }
+
+    pub fn sso_blocked_emails_vec(&self) -> Vec<String> {
+        self.sso_blocked_emails().split(',').map(str::trim).filter(|e| !e.is_empty()).map(str::to_lowercase).collect()
+    }
+
+    pub fn sso_allowed_signing_algs_vec(&self) -> Vec<String> {
+        internal_sso_allowed_signing_algs_vec(&self.sso_allowed_signing_algs())
+    }
+
+    pub fn sso_id_token_decryption_keys_vec(&self) -> Vec<String> {
+        internal_sso_id_token_decryption_keys_vec(&self.sso_id_token_decryption_keys())
+    }
+
     pub fn sso_organizations_id_mapping_map(&self) -> HashMap<String, Either<String, OrganizationId>> {
         parse_as_hashmap(self.sso_organizations_id_mapping(), |str| match Uuid::parse_str(&str) {
             Ok(_) => Either::Right(str.into()),
             Err(_) => Either::Left(str),
         })
     }
+
+    pub fn sso_trusted_device_orgs_vec(&self) -> Vec<OrganizationId> {
+        internal_sso_trusted_device_orgs_vec(&self.sso_trusted_device_orgs())
+            .into_iter()
+            .map(OrganizationId::from)
+            .collect()
+    }
+
+    pub fn sso_key_connector_urls_map(&self) -> HashMap<OrganizationId, String> {
+        // Validated on load
+        internal_sso_key_connector_urls_map(&self.sso_key_connector_urls()).unwrap_or_default()
+    }
 }
 
 use handlebars::{
@@ -1595,6 +2149,7 @@ where
     reg!("email/send_single_org_removed_from_org", ".html");
     reg!("email/smtp_test", ".html");
     reg!("email/sso_change_email", ".html");
+    reg!("email/sso_link_confirmation", ".html");
     reg!("email/twofactor_email", ".html");
     reg!("email/verify_email", ".html");
     reg!("email/welcome_must_verify", ".html");
@@ -1608,6 +2163,8 @@ where
     reg!("admin/diagnostics");
 
     reg!("404");
+    reg!("sso_error");
+    reg!("sso_link");
 
     reg!(@withfallback "scss/vaultwarden.scss");
     reg!("scss/user.vaultwarden.scss");
@@ -1695,9 +2252,34 @@ handlebars::handlebars_helper!(vwver: | vw_version: String |
 );
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
 
+    // Organization using the Key Connector of the test configuration
+    pub const TEST_KEY_CONNECTOR_ORG_ID: &str = "6b1c4f0e-3e8a-4d7c-9a2b-5f1e0d9c8b7a";
+
+    // Temporary data folder (database, keys) and a test provider, the provider is never contacted
+    impl ConfigBuilder {
+        pub(super) fn test_values() -> Self {
+            let data_folder = std::env::temp_dir().join(format!("vaultwarden-tests-{}", std::process::id()));
+            std::fs::create_dir_all(&data_folder).expect("Failed to create the test data folder");
+
+            ConfigBuilder {
+                data_folder: Some(data_folder.to_string_lossy().to_string()),
+                domain: Some("https://vault.example.com".to_string()),
+                sso_enabled: Some(true),
+                sso_authority: Some("https://idp.example.com".to_string()),
+                sso_client_id: Some("vaultwarden".to_string()),
+                sso_client_secret: Some("secret".to_string()),
+                // Stub providers listen on a random local port, without TLS
+                sso_issuer_trusted: Some(r"^http://127\.0\.0\.1:[0-9]+$".to_string()),
+                sso_allow_insecure_endpoints: Some(true),
+                sso_key_connector_urls: Some(format!("{TEST_KEY_CONNECTOR_ORG_ID}:https://kc.example.com")),
+                ..Default::default()
+            }
+        }
+    }
+
     #[test]
     fn test_parse_param_list() {
         let config = "key1=value&key2=value2&".to_string();
@@ -1743,4 +2325,54 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_sso_authorize_extra_params() {
+        assert_eq!(
+            internal_sso_authorize_extra_params_vec("resource=https://api&audience=vault").unwrap(),
+            vec![("resource".to_string(), "https://api".to_string()), ("audience".to_string(), "vault".to_string())]
+        );
+        assert!(internal_sso_authorize_extra_params_vec("").unwrap().is_empty());
+
+        for reserved in ["client_id", "state", "nonce", "redirect_uri", "scope", "response_type", "Code_Challenge"] {
+            assert!(internal_sso_authorize_extra_params_vec(&format!("prompt=login&{reserved}=x")).is_err());
+        }
+    }
+
+    #[test]
+    fn test_sso_request_claims() {
+        assert_eq!(
+            internal_sso_request_claims(r#"{ "id_token": { "email": { "essential": true } }, "userinfo": {} }"#)
+                .unwrap(),
+            r#"{"id_token":{"email":{"essential":true}},"userinfo":{}}"#
+        );
+
+        assert!(internal_sso_request_claims("email").is_err());
+        assert!(internal_sso_request_claims(r#"["email"]"#).is_err());
+        assert!(internal_sso_request_claims(r#"{ "access_token": {} }"#).is_err());
+        assert!(internal_sso_request_claims(r#"{ "id_token": "email" }"#).is_err());
+    }
+
+    #[test]
+    fn test_sso_client_secret() {
+        assert_eq!(internal_sso_client_secret(" secret\n").unwrap(), "secret");
+        assert_eq!(internal_sso_client_secret("base64:c2VjcmV0Cg==").unwrap(), "secret");
+        assert!(internal_sso_client_secret("base64:not base64").is_err());
+        assert!(internal_sso_client_secret(" ").is_err());
+    }
+
+    #[test]
+    fn test_sso_key_connector_urls() {
+        let org_id = "2a4f6c7e-1b2d-4e5f-8a9b-0c1d2e3f4a5b";
+        let urls = internal_sso_key_connector_urls_map(&format!(" {org_id}:https://kc.example.com/ ;")).unwrap();
+        assert_eq!(
+            urls.get(&OrganizationId::from(org_id.to_string())).map(String::as_str),
+            Some("https://kc.example.com")
+        );
+
+        assert!(internal_sso_key_connector_urls_map("").unwrap().is_empty());
+        assert!(internal_sso_key_connector_urls_map("https://kc.example.com").is_err());
+        assert!(internal_sso_key_connector_urls_map(&format!("{org_id}:http://kc.example.com")).is_err());
+        assert!(internal_sso_key_connector_urls_map("my-org:https://kc.example.com").is_err());
+    }
 }
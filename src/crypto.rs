@@ -33,6 +33,65 @@ pub fn hmac_sign(key: &str, data: &str) -> String {
     HEXLOWER.encode(signature.as_ref())
 }
 
+pub fn hmac_sha256_sign(key: &str, data: &str) -> String {
+    hmac_sha256_sign_bytes(key.as_bytes(), data)
+}
+
+pub fn hmac_sha256_sign_bytes(key: &[u8], data: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+    let signature = hmac::sign(&key, data.as_bytes());
+
+    HEXLOWER.encode(signature.as_ref())
+}
+
+//
+// AES-256-GCM
+//
+
+/// Derive an AES-256 key from the secret using HKDF-SHA256, `info` is used for domain separation.
+pub fn derive_aes_key(secret: &[u8], info: &[u8]) -> [u8; 32] {
+    use ring::hkdf;
+
+    let mut key = [0u8; 32];
+    hkdf::Salt::new(hkdf::HKDF_SHA256, &[])
+        .extract(secret)
+        .expand(&[info], hkdf::HKDF_SHA256)
+        .and_then(|okm| okm.fill(&mut key))
+        .expect("HKDF output length is valid");
+    key
+}
+
+/// Encrypt the data, the random nonce is prepended to the ciphertext.
+pub fn aes_gcm_encrypt(key: &[u8; 32], data: &[u8]) -> Vec<u8> {
+    use ring::aead;
+
+    let key = aead::LessSafeKey::new(aead::UnboundKey::new(&aead::AES_256_GCM, key).expect("Key length is valid"));
+    let nonce = get_random_bytes::<{ aead::NONCE_LEN }>();
+
+    let mut in_out = data.to_vec();
+    key.seal_in_place_append_tag(aead::Nonce::assume_unique_for_key(nonce), aead::Aad::empty(), &mut in_out)
+        .expect("Error encrypting data");
+
+    [nonce.as_slice(), &in_out].concat()
+}
+
+/// Decrypt data produced by `aes_gcm_encrypt`, returns `None` if the data was tampered with or the key is wrong.
+pub fn aes_gcm_decrypt(key: &[u8; 32], data: &[u8]) -> Option<Vec<u8>> {
+    use ring::aead;
+
+    if data.len() < aead::NONCE_LEN {
+        return None;
+    }
+
+    let key = aead::LessSafeKey::new(aead::UnboundKey::new(&aead::AES_256_GCM, key).ok()?);
+    let (nonce, ciphertext) = data.split_at(aead::NONCE_LEN);
+    let nonce = aead::Nonce::try_assume_unique_for_key(nonce).ok()?;
+
+    let mut in_out = ciphertext.to_vec();
+    let plaintext = key.open_in_place(nonce, aead::Aad::empty(), &mut in_out).ok()?;
+    Some(plaintext.to_vec())
+}
+
 //
 // Random values
 //
@@ -52,6 +111,16 @@ pub fn encode_random_bytes<const N: usize>(e: Encoding) -> String {
     e.encode(&get_random_bytes::<N>())
 }
 
+/// Encode `len` random bytes using the provided function, for lengths only known at runtime.
+pub fn encode_random_bytes_len(e: Encoding, len: usize) -> String {
+    use ring::rand::{SecureRandom, SystemRandom};
+
+    let mut bytes = vec![0; len];
+    SystemRandom::new().fill(&mut bytes).expect("Error generating random values");
+
+    e.encode(&bytes)
+}
+
 /// Generates a random string over a specified alphabet.
 pub fn get_random_string(alphabet: &[u8], num_chars: usize) -> String {
     // Ref: https://rust-lang-nursery.github.io/rust-cookbook/algorithms/randomness.html
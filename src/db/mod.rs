@@ -404,6 +404,17 @@ pub async fn get_sql_server_version(conn: &mut DbConn) -> String {
     }
 }
 
+/// Connection to the test database, the test configuration uses a temporary data folder
+#[cfg(all(test, sqlite))]
+pub async fn test_conn() -> DbConn {
+    static POOL: once_cell::sync::Lazy<DbPool> = once_cell::sync::Lazy::new(|| {
+        crate::auth::initialize_keys().expect("Failed to create the test keys");
+        DbPool::from_config().expect("Failed to create the test database")
+    });
+
+    POOL.get().await.expect("Failed to get a test database connection")
+}
+
 /// Attempts to retrieve a single connection from the managed database pool. If
 /// no pool is currently managed, fails with an `InternalServerError` status. If
 /// no connections are available, fails with a `ServiceUnavailable` status.
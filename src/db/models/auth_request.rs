@@ -66,6 +66,11 @@ impl AuthRequest {
         }
     }
 
+    // Device approval requested to the organization admins (trusted device encryption)
+    pub fn is_admin_request(&self) -> bool {
+        self.organization_uuid.is_some()
+    }
+
     pub fn to_json_for_pending_device(&self) -> Value {
         json!({
             "id": self.uuid,
@@ -156,6 +161,31 @@ impl AuthRequest {
         }}
     }
 
+    pub async fn find_pending_by_org(org_uuid: &OrganizationId, conn: &mut DbConn) -> Vec<Self> {
+        db_run! {conn: {
+            auth_requests::table
+                .filter(auth_requests::organization_uuid.eq(org_uuid))
+                .filter(auth_requests::approved.is_null())
+                .order_by(auth_requests::creation_date.desc())
+                .load::<AuthRequestDb>(conn).expect("Error loading auth_requests").from_db()
+        }}
+    }
+
+    pub async fn find_by_uuid_and_org(
+        uuid: &AuthRequestId,
+        org_uuid: &OrganizationId,
+        conn: &mut DbConn,
+    ) -> Option<Self> {
+        db_run! {conn: {
+            auth_requests::table
+                .filter(auth_requests::uuid.eq(uuid))
+                .filter(auth_requests::organization_uuid.eq(org_uuid))
+                .first::<AuthRequestDb>(conn)
+                .ok()
+                .from_db()
+        }}
+    }
+
     pub async fn find_created_before(dt: &NaiveDateTime, conn: &mut DbConn) -> Vec<Self> {
         db_run! {conn: {
             auth_requests::table
@@ -177,9 +207,13 @@ impl AuthRequest {
     }
 
     pub async fn purge_expired_auth_requests(conn: &mut DbConn) {
-        let expiry_time = Utc::now().naive_utc() - chrono::TimeDelta::try_minutes(5).unwrap(); //after 5 minutes, clients reject the request
+        let now = Utc::now().naive_utc();
+        let expiry_time = now - chrono::TimeDelta::try_minutes(5).unwrap(); //after 5 minutes, clients reject the request
+        let admin_expiry_time = now - chrono::TimeDelta::try_days(7).unwrap(); // admin approvals can take longer
         for auth_request in Self::find_created_before(&expiry_time, conn).await {
-            auth_request.delete(conn).await.ok();
+            if !auth_request.is_admin_request() || auth_request.creation_date < admin_expiry_time {
+                auth_request.delete(conn).await.ok();
+            }
         }
     }
 }
@@ -1,4 +1,5 @@
 use chrono::{NaiveDateTime, Utc};
+use std::collections::HashMap;
 
 use data_encoding::{BASE64, BASE64URL};
 use derive_more::{Display, From};
@@ -30,6 +31,20 @@ db_object! {
 
         pub refresh_token: String,
         pub twofactor_remember: Option<String>,
+
+        // Trusted device encryption, the user key encrypted with the device key and the device keys
+        pub encrypted_user_key: Option<String>,
+        pub encrypted_public_key: Option<String>,
+        pub encrypted_private_key: Option<String>,
+
+        // Expiration of the provider session of the last SSO login or refresh (`SSO_SESSION_CAP_IDP_EXPIRY`)
+        pub sso_expires_at: Option<NaiveDateTime>,
+        // The last login of this device used SSO
+        pub sso_login: bool,
+        // Provider session (`sid` claim of the id_token) of the last SSO login, matched by the back-channel logout
+        pub sso_sid: Option<String>,
+        // Encrypted provider token of the session, revoked at the provider when the account is deleted or deauthorized
+        pub sso_token: Option<String>,
     }
 }
 
@@ -42,11 +57,22 @@ impl Device {
             "type": self.atype,
             "identifier": self.uuid,
             "creationDate": format_date(&self.created_at),
-            "isTrusted": false,
+            "isTrusted": self.is_trusted(),
+            "isSso": self.sso_login,
             "object":"device"
         })
     }
 
+    pub fn is_trusted(&self) -> bool {
+        self.encrypted_user_key.is_some() && self.encrypted_public_key.is_some()
+    }
+
+    pub fn delete_trust(&mut self) {
+        self.encrypted_user_key = None;
+        self.encrypted_public_key = None;
+        self.encrypted_private_key = None;
+    }
+
     pub fn refresh_twofactor_remember(&mut self) -> String {
         let twofactor_remember = crypto::encode_random_bytes::<180>(BASE64);
         self.twofactor_remember = Some(twofactor_remember.clone());
@@ -54,10 +80,39 @@ impl Device {
         twofactor_remember
     }
 
+    // End the session of the device, its current refresh token will be refused
+    pub fn rotate_refresh_token(&mut self) {
+        self.refresh_token = crypto::encode_random_bytes::<64>(BASE64URL);
+    }
+
     pub fn delete_twofactor_remember(&mut self) {
         self.twofactor_remember = None;
     }
 
+    // Never saved, attribute the events of the changes made without a client (SCIM provisioning)
+    pub fn server(user_uuid: UserId) -> Self {
+        let now = Utc::now().naive_utc();
+        Self {
+            uuid: DeviceId(get_uuid()),
+            created_at: now,
+            updated_at: now,
+            user_uuid,
+            name: "Server".to_string(),
+            atype: DeviceType::Server as i32,
+            push_uuid: None,
+            push_token: None,
+            refresh_token: String::new(),
+            twofactor_remember: None,
+            encrypted_user_key: None,
+            encrypted_public_key: None,
+            encrypted_private_key: None,
+            sso_expires_at: None,
+            sso_login: false,
+            sso_sid: None,
+            sso_token: None,
+        }
+    }
+
     // This rely on the fact we only update the device after a successful login
     pub fn is_new(&self) -> bool {
         self.created_at == self.updated_at
@@ -90,9 +145,10 @@ impl DeviceWithAuthRequest {
             "identifier": self.device.uuid,
             "creationDate": format_date(&self.device.created_at),
             "devicePendingAuthRequest": auth_request,
-            "isTrusted": false,
-            "encryptedPublicKey": null,
-            "encryptedUserKey": null,
+            "isTrusted": self.device.is_trusted(),
+            "isSso": self.device.sso_login,
+            "encryptedPublicKey": self.device.encrypted_public_key,
+            "encryptedUserKey": self.device.encrypted_user_key,
             "object": "device",
         })
     }
@@ -133,6 +189,13 @@ impl Device {
             push_token: None,
             refresh_token: crypto::encode_random_bytes::<64>(BASE64URL),
             twofactor_remember: None,
+            encrypted_user_key: None,
+            encrypted_public_key: None,
+            encrypted_private_key: None,
+            sso_expires_at: None,
+            sso_login: false,
+            sso_sid: None,
+            sso_token: None,
         };
 
         device.inner_save(conn).await.map(|()| device)
@@ -162,6 +225,29 @@ impl Device {
         self.inner_save(conn).await
     }
 
+    // Invalidate all the sessions and forget their provider session, the SSO identity was removed.
+    // The devices are kept (push registration, trusted devices).
+    pub async fn unlink_sso_by_user(user_uuid: &UserId, conn: &mut DbConn) -> EmptyResult {
+        for mut device in Self::find_by_user(user_uuid, conn).await {
+            device.rotate_refresh_token();
+            device.sso_token = None;
+            device.sso_sid = None;
+            device.save(conn).await?;
+        }
+        Ok(())
+    }
+
+    // The device keys are only valid with the current user key
+    pub async fn delete_trust_by_user(user_uuid: &UserId, conn: &mut DbConn) -> EmptyResult {
+        for mut device in Self::find_by_user(user_uuid, conn).await {
+            if device.is_trusted() {
+                device.delete_trust();
+                device.save(conn).await?;
+            }
+        }
+        Ok(())
+    }
+
     pub async fn delete_all_by_user(user_uuid: &UserId, conn: &mut DbConn) -> EmptyResult {
         db_run! { conn: {
             diesel::delete(devices::table.filter(devices::user_uuid.eq(user_uuid)))
@@ -230,6 +316,35 @@ impl Device {
         }}
     }
 
+    // A provider session can be used to login on several devices
+    pub async fn find_by_sso_sid(sid: &str, conn: &mut DbConn) -> Vec<Self> {
+        db_run! { conn: {
+            devices::table
+                .filter(devices::sso_sid.eq(sid))
+                .load::<DeviceDb>(conn)
+                .expect("Error loading devices")
+                .from_db()
+        }}
+    }
+
+    /// Return the number of SSO sessions of each user
+    /// This is used by the admin users overview so we only need one query for all the users.
+    pub async fn count_sso_sessions_by_user(conn: &mut DbConn) -> HashMap<UserId, usize> {
+        let user_uuids = db_run! { conn: {
+            devices::table
+                .filter(devices::sso_login.eq(true))
+                .select(devices::user_uuid)
+                .load::<UserId>(conn)
+                .unwrap_or_default()
+        }};
+
+        let mut sessions = HashMap::new();
+        for user_uuid in user_uuids {
+            *sessions.entry(user_uuid).or_insert(0) += 1;
+        }
+        sessions
+    }
+
     pub async fn find_latest_active_by_user(user_uuid: &UserId, conn: &mut DbConn) -> Option<Self> {
         db_run! { conn: {
             devices::table
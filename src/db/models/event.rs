@@ -33,6 +33,10 @@ db_object! {
         pub provider_uuid: Option<String>,
         pub provider_user_uuid: Option<String>,
         pub provider_org_uuid: Option<String>,
+        // SSO login events: host of the issuer, provider subject and account kind or refusal reason
+        pub sso_provider: Option<String>,
+        pub sso_subject: Option<String>,
+        pub sso_detail: Option<String>,
     }
 }
 
@@ -49,7 +53,7 @@ pub enum EventType {
     UserFailedLogIn2fa = 1006,
     UserClientExportedVault = 1007,
     // UserUpdatedTempPassword = 1008, // Not supported
-    // UserMigratedKeyToKeyConnector = 1009, // Not supported
+    UserMigratedKeyToKeyConnector = 1009,
     UserRequestedDeviceApproval = 1010,
     // UserTdeOffboardingPasswordSet = 1011, // Not supported
 
@@ -94,7 +98,7 @@ pub enum EventType {
     OrganizationUserResetPasswordWithdraw = 1507,
     OrganizationUserAdminResetPassword = 1508,
     // OrganizationUserResetSsoLink = 1509, // Not supported
-    // OrganizationUserFirstSsoLogin = 1510, // Not supported
+    OrganizationUserFirstSsoLogin = 1510,
     OrganizationUserRevoked = 1511,
     OrganizationUserRestored = 1512,
     OrganizationUserApprovedAuthRequest = 1513,
@@ -159,6 +163,9 @@ impl Event {
             provider_uuid: None,
             provider_user_uuid: None,
             provider_org_uuid: None,
+            sso_provider: None,
+            sso_subject: None,
+            sso_detail: None,
         }
     }
 
@@ -182,6 +189,9 @@ impl Event {
             "providerUserId": self.provider_user_uuid,
             "providerOrganizationId": self.provider_org_uuid,
             // "installationId": null, // Not supported
+            "ssoProvider": self.sso_provider,
+            "ssoSubject": self.sso_subject,
+            "ssoDetail": self.sso_detail,
         })
     }
 }
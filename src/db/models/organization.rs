@@ -49,6 +49,12 @@ db_object! {
         pub atype: i32,
         pub reset_password_key: Option<String>,
         pub external_id: Option<String>,
+        // Last SSO login with the mapped organization in the provider groups
+        pub sso_seen_at: Option<NaiveDateTime>,
+        // Not seen on a SSO login for `SSO_ORGANIZATIONS_STALE_DAYS`, cleared on the next login with the group
+        pub sso_stale: bool,
+        // Added to the organization by a SCIM group
+        pub scim_managed: bool,
     }
 
     #[derive(Identifiable, Queryable, Insertable, AsChangeset)]
@@ -202,7 +208,7 @@ impl Organization {
             "usePolicies": true,
             "useScim": false, // Not supported (Not AGPLv3 Licensed)
             "useSso": false, // Not supported
-            "useKeyConnector": false, // Not supported
+            "useKeyConnector": crate::sso::key_connector_url(&self.uuid).is_some(),
             "usePasswordManager": true,
             "useSecretsManager": false, // Not supported (Not AGPLv3 Licensed)
             "selfHost": true,
@@ -260,6 +266,9 @@ impl Membership {
             atype: MembershipType::User as i32,
             reset_password_key: None,
             external_id: None,
+            sso_seen_at: None,
+            sso_stale: false,
+            scim_managed: false,
         }
     }
 
@@ -267,6 +276,11 @@ impl Membership {
         self.status < MembershipStatus::Invited as i32
     }
 
+    // Synced from the provider groups or pushed by SCIM, the other members are managed by the organization admins
+    pub fn is_provider_managed(&self) -> bool {
+        self.sso_seen_at.is_some() || self.scim_managed
+    }
+
     pub fn restore(&mut self) -> bool {
         if self.is_revoked() {
             self.status += ACTIVATE_REVOKE_DIFF;
@@ -535,6 +549,8 @@ impl Organization {
 impl Membership {
     pub async fn to_json(&self, conn: &mut DbConn) -> Value {
         let org = Organization::find_by_uuid(&self.org_uuid, conn).await.unwrap();
+        let key_connector_url = crate::sso::key_connector_url(&self.org_uuid);
+        let trusted_devices = crate::sso::trusted_device_encryption(&self.org_uuid);
 
         // HACK: Convert the manager type to a custom type
         // It will be converted back on other locations
@@ -579,9 +595,13 @@ impl Membership {
             "hasPublicAndPrivateKeys": org.private_key.is_some() && org.public_key.is_some(),
             "resetPasswordEnrolled": self.reset_password_key.is_some(),
             "useResetPassword": CONFIG.mail_enabled(),
-            "ssoBound": false, // Not supported
-            "useSso": false, // Not supported
-            "useKeyConnector": false,
+            // Only bound when the organization uses a member decryption option, required to display the device approvals
+            "ssoBound": trusted_devices || key_connector_url.is_some(),
+            // Required by the web vault to display the Require SSO policy
+            "useSso": CONFIG.sso_enabled() || trusted_devices || key_connector_url.is_some(),
+            // 0: Master password, 1: Key Connector, 2: Trusted device encryption
+            "ssoMemberDecryptionType": if key_connector_url.is_some() { 1 } else if trusted_devices { 2 } else { 0 },
+            "useKeyConnector": key_connector_url.is_some(),
             "useSecretsManager": false, // Not supported (Not AGPLv3 Licensed)
             "usePasswordManager": true,
             "useCustomPermissions": true,
@@ -596,8 +616,8 @@ impl Membership {
             "familySponsorshipFriendlyName": null,
             "familySponsorshipAvailable": false,
             "productTierType": 3, // Enterprise tier
-            "keyConnectorEnabled": false,
-            "keyConnectorUrl": null,
+            "keyConnectorEnabled": key_connector_url.is_some(),
+            "keyConnectorUrl": key_connector_url,
             "familySponsorshipLastSyncDate": null,
             "familySponsorshipValidUntil": null,
             "familySponsorshipToDelete": null,
@@ -755,8 +775,9 @@ impl Membership {
             "ssoBound": false, // Not supported
             "managedByOrganization": false, // This key is obsolete replaced by claimedByOrganization
             "claimedByOrganization": false, // Means not managed via the Members UI, like SSO
-            "usesKeyConnector": false, // Not supported
+            "usesKeyConnector": user.uses_key_connector,
             "accessSecretsManager": false, // Not supported (Not AGPLv3 Licensed)
+            "ssoStale": self.sso_stale,
 
             "object": "organizationUserUserDetails",
         })
@@ -1085,6 +1106,20 @@ impl Membership {
         }}
     }
 
+    // Memberships synced from the provider groups which were not seen on a login since `older_than` and not flagged yet,
+    // owners are excluded
+    pub async fn find_sso_stale(older_than: &NaiveDateTime, conn: &mut DbConn) -> Vec<Self> {
+        db_run! { conn: {
+            users_organizations::table
+                .filter(users_organizations::sso_seen_at.lt(older_than))
+                .filter(users_organizations::sso_stale.eq(false))
+                .filter(users_organizations::status.ge(MembershipStatus::Invited as i32))
+                .filter(users_organizations::atype.ne(MembershipType::Owner as i32))
+                .load::<MembershipDb>(conn)
+                .unwrap_or_default().from_db()
+        }}
+    }
+
     pub async fn find_confirmed_by_user_and_org(
         user_uuid: &UserId,
         org_uuid: &OrganizationId,
@@ -1,11 +1,13 @@
 use chrono::{NaiveDateTime, Utc};
 
 use crate::api::EmptyResult;
-use crate::db::{DbConn, DbPool};
+use crate::db::{models::DeviceId, DbConn, DbPool};
 use crate::error::MapResult;
-use crate::sso::{OIDCState, NONCE_EXPIRATION};
+use crate::sso::{nonce_lifetime, OIDCState};
 
 db_object! {
+    // One row per flow, from `authorize` to `redeem`. The `state` is the primary key: the `CsrfToken` sent to the
+    // provider is its base64 encoding and the id_token `nonce` is checked against the row resolved at the callback.
     #[derive(Identifiable, Queryable, Insertable)]
     #[diesel(table_name = sso_nonce)]
     #[diesel(primary_key(state))]
@@ -15,12 +17,23 @@ db_object! {
         pub verifier: Option<String>,
         pub redirect_uri: String,
         pub created_at: NaiveDateTime,
+        pub authenticated_user: Option<String>,
+        pub correlation_id: Option<String>,
+        pub return_path: Option<String>,
+        pub device_id: Option<DeviceId>,
+        pub client_type: Option<String>,
     }
 }
 
 /// Local methods
 impl SsoNonce {
-    pub fn new(state: OIDCState, nonce: String, verifier: Option<String>, redirect_uri: String) -> Self {
+    pub fn new(
+        state: OIDCState,
+        nonce: String,
+        verifier: Option<String>,
+        redirect_uri: String,
+        correlation_id: String,
+    ) -> Self {
         let now = Utc::now().naive_utc();
 
         SsoNonce {
@@ -29,8 +42,17 @@ impl SsoNonce {
             verifier,
             redirect_uri,
             created_at: now,
+            authenticated_user: None,
+            correlation_id: Some(correlation_id),
+            return_path: None,
+            device_id: None,
+            client_type: None,
         }
     }
+
+    pub fn is_expired(&self) -> bool {
+        self.created_at < Utc::now().naive_utc() - nonce_lifetime()
+    }
 }
 
 /// Database methods
@@ -53,16 +75,38 @@ impl SsoNonce {
         }
     }
 
-    pub async fn delete(state: &OIDCState, conn: &mut DbConn) -> EmptyResult {
+    pub async fn set_authenticated_user(
+        state: &OIDCState,
+        authenticated_user: String,
+        conn: &mut DbConn,
+    ) -> EmptyResult {
         db_run! { conn: {
-            diesel::delete(sso_nonce::table.filter(sso_nonce::state.eq(state)))
+            diesel::update(sso_nonce::table.filter(sso_nonce::state.eq(state)))
+                .set(sso_nonce::authenticated_user.eq(authenticated_user))
                 .execute(conn)
-                .map_res("Error deleting SSO nonce")
+                .map_res("Error saving SSO nonce authenticated user")
         }}
     }
 
-    pub async fn find(state: &OIDCState, conn: &DbConn) -> Option<Self> {
-        let oldest = Utc::now().naive_utc() - *NONCE_EXPIRATION;
+    // Only the caller which deleted the row gets the nonce, a concurrent take of the same flow gets `None`
+    pub async fn take(state: &OIDCState, conn: &mut DbConn) -> Option<Self> {
+        let nonce = Self::find_by_state(state, conn).await?;
+        let deleted = db_run! { conn: {
+            diesel::delete(sso_nonce::table.filter(sso_nonce::state.eq(state)))
+                .execute(conn)
+        }};
+        match deleted {
+            Ok(1) => Some(nonce),
+            Ok(_) => None,
+            Err(err) => {
+                error!("Failed to delete database sso_nonce using {state}: {err}");
+                None
+            }
+        }
+    }
+
+    pub async fn find_by_state(state: &OIDCState, conn: &DbConn) -> Option<Self> {
+        let oldest = Utc::now().naive_utc() - nonce_lifetime();
         db_run! { conn: {
             sso_nonce::table
                 .filter(sso_nonce::state.eq(state))
@@ -73,10 +117,41 @@ impl SsoNonce {
         }}
     }
 
+    // Including the expired ones not purged yet, most recent first
+    pub async fn find_all(conn: &mut DbConn) -> Vec<Self> {
+        db_run! { conn: {
+            sso_nonce::table
+                .order(sso_nonce::created_at.desc())
+                .load::<SsoNonceDb>(conn)
+                .expect("Error loading SSO nonces")
+                .from_db()
+        }}
+    }
+
+    pub async fn delete_all(conn: &mut DbConn) -> EmptyResult {
+        db_run! { conn: {
+            diesel::delete(sso_nonce::table)
+                .execute(conn)
+                .map_res("Error deleting SSO nonces")
+        }}
+    }
+
+    pub async fn count_active(conn: &mut DbConn) -> i64 {
+        let oldest = Utc::now().naive_utc() - nonce_lifetime();
+        db_run! { conn: {
+            sso_nonce::table
+                .filter(sso_nonce::created_at.ge(oldest))
+                .count()
+                .first::<i64>(conn)
+                .ok()
+                .unwrap_or(0)
+        }}
+    }
+
     pub async fn delete_expired(pool: DbPool) -> EmptyResult {
         debug!("Purging expired sso_nonce");
         if let Ok(conn) = pool.get().await {
-            let oldest = Utc::now().naive_utc() - *NONCE_EXPIRATION;
+            let oldest = Utc::now().naive_utc() - nonce_lifetime();
             db_run! { conn: {
                 diesel::delete(sso_nonce::table.filter(sso_nonce::created_at.lt(oldest)))
                     .execute(conn)
@@ -3,7 +3,8 @@ use derive_more::{AsRef, Deref, Display, From};
 use serde_json::Value;
 
 use super::{
-    Cipher, Device, EmergencyAccess, Favorite, Folder, Membership, MembershipType, TwoFactor, TwoFactorIncomplete,
+    Cipher, Device, EmergencyAccess, Favorite, Folder, Membership, MembershipStatus, MembershipType, OrganizationId,
+    TwoFactor, TwoFactorIncomplete,
 };
 use crate::{
     api::EmptyResult,
@@ -65,6 +66,9 @@ db_object! {
         pub avatar_color: Option<String>,
 
         pub external_id: Option<String>, // Todo: Needs to be removed in the future, this is not used anymore.
+
+        // The master key is stored in the organization Key Connector, the account has no master password
+        pub uses_key_connector: bool,
     }
 
     #[derive(Identifiable, Queryable, Insertable)]
@@ -151,6 +155,8 @@ impl User {
             avatar_color: None,
 
             external_id: None, // Todo: Needs to be removed in the future, this is not used anymore.
+
+            uses_key_connector: false,
         }
     }
 
@@ -163,6 +169,12 @@ impl User {
         )
     }
 
+    /// A user is registered once it has a master password, stores its master key in a Key Connector
+    /// or has keys without master password (trusted device encryption)
+    pub fn is_registered(&self) -> bool {
+        !self.password_hash.is_empty() || self.uses_key_connector || self.private_key.is_some()
+    }
+
     pub fn check_valid_recovery_code(&self, recovery_code: &str) -> bool {
         if let Some(ref totp_recover) = self.totp_recover {
             crypto::ct_eq(recovery_code, totp_recover.to_lowercase())
@@ -245,7 +257,7 @@ impl User {
         let twofactor_enabled = !TwoFactor::find_by_user(&self.uuid, conn).await.is_empty();
 
         // TODO: Might want to save the status field in the DB
-        let status = if self.password_hash.is_empty() {
+        let status = if !self.is_registered() {
             UserStatus::Invited
         } else {
             UserStatus::Enabled
@@ -269,7 +281,7 @@ impl User {
             "providerOrganizations": [],
             "forcePasswordReset": false,
             "avatarColor": self.avatar_color,
-            "usesKeyConnector": false,
+            "usesKeyConnector": self.uses_key_connector,
             "creationDate": format_date(&self.created_at),
             "object": "profile",
         })
@@ -406,6 +418,20 @@ impl User {
         }}
     }
 
+    // Users with a non revoked membership in the organization
+    pub async fn find_by_org(org_uuid: &OrganizationId, conn: &mut DbConn) -> Vec<Self> {
+        db_run! { conn: {
+            users::table
+                .inner_join(users_organizations::table.on(users_organizations::user_uuid.eq(users::uuid)))
+                .filter(users_organizations::org_uuid.eq(org_uuid))
+                .filter(users_organizations::status.ge(MembershipStatus::Invited as i32))
+                .select(users::all_columns)
+                .load::<UserDb>(conn)
+                .expect("Error loading organization users")
+                .from_db()
+        }}
+    }
+
     pub async fn get_all(conn: &mut DbConn) -> Vec<(User, Option<SsoUser>)> {
         db_run! {conn: {
             users::table
@@ -538,15 +564,27 @@ impl SsoUser {
         }}
     }
 
+    // Accounts saved with a mixed case email (before emails were lowercased) are found with a case-insensitive lookup
     pub async fn find_by_mail(mail: &str, conn: &DbConn) -> Option<(User, Option<SsoUser>)> {
-        let lower_mail = mail.to_lowercase();
+        let lower_mail = mail.trim().to_lowercase();
 
         db_run! {conn: {
+            define_sql_function!{
+                fn lower(x: diesel::sql_types::Text) -> diesel::sql_types::Text;
+            }
+
             users::table
                 .left_join(sso_users::table)
                 .select(<(UserDb, Option<SsoUserDb>)>::as_select())
-                .filter(users::email.eq(lower_mail))
+                .filter(users::email.eq(&lower_mail))
                 .first::<(UserDb, Option<SsoUserDb>)>(conn)
+                .or_else(|_| {
+                    users::table
+                        .left_join(sso_users::table)
+                        .select(<(UserDb, Option<SsoUserDb>)>::as_select())
+                        .filter(lower(users::email).eq(&lower_mail))
+                        .first::<(UserDb, Option<SsoUserDb>)>(conn)
+                })
                 .ok()
                 .map(|(user, sso_user)| { (user.from_db(), sso_user.from_db()) })
         }}
@@ -55,6 +55,13 @@ table! {
         push_token -> Nullable<Text>,
         refresh_token -> Text,
         twofactor_remember -> Nullable<Text>,
+        encrypted_user_key -> Nullable<Text>,
+        encrypted_public_key -> Nullable<Text>,
+        encrypted_private_key -> Nullable<Text>,
+        sso_expires_at -> Nullable<Timestamp>,
+        sso_login -> Bool,
+        sso_sid -> Nullable<Text>,
+        sso_token -> Nullable<Text>,
     }
 }
 
@@ -76,6 +83,9 @@ table! {
         provider_uuid -> Nullable<Text>,
         provider_user_uuid -> Nullable<Text>,
         provider_org_uuid -> Nullable<Text>,
+        sso_provider -> Nullable<Text>,
+        sso_subject -> Nullable<Text>,
+        sso_detail -> Nullable<Text>,
     }
 }
 
@@ -218,6 +228,7 @@ table! {
         api_key -> Nullable<Text>,
         avatar_color -> Nullable<Text>,
         external_id -> Nullable<Text>,
+        uses_key_connector -> Bool,
     }
 }
 
@@ -243,6 +254,9 @@ table! {
         atype -> Integer,
         reset_password_key -> Nullable<Text>,
         external_id -> Nullable<Text>,
+        sso_seen_at -> Nullable<Timestamp>,
+        sso_stale -> Bool,
+        scim_managed -> Bool,
     }
 }
 
@@ -263,6 +277,11 @@ table! {
         verifier -> Nullable<Text>,
         redirect_uri -> Text,
         created_at -> Timestamp,
+        authenticated_user -> Nullable<Text>,
+        correlation_id -> Nullable<Text>,
+        return_path -> Nullable<Text>,
+        device_id -> Nullable<Text>,
+        client_type -> Nullable<Text>,
     }
 }
 
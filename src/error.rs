@@ -13,14 +13,14 @@ macro_rules! make_error {
 
         #[derive(Debug)]
         pub struct ErrorEvent { pub event: EventType }
-        pub struct Error { message: String, error: ErrorKind, error_code: u16, event: Option<ErrorEvent> }
+        pub struct Error { message: String, error: ErrorKind, error_code: u16, event: Option<ErrorEvent>, retry_after: Option<u64> }
 
         $(impl From<$ty> for Error {
             fn from(err: $ty) -> Self { Error::from((stringify!($name), err)) }
         })+
         $(impl<S: Into<String>> From<(S, $ty)> for Error {
             fn from(val: (S, $ty)) -> Self {
-                Error { message: val.0.into(), error: ErrorKind::$name(val.1), error_code: BAD_REQUEST, event: None }
+                Error { message: val.0.into(), error: ErrorKind::$name(val.1), error_code: BAD_REQUEST, event: None, retry_after: None }
             }
         })+
         impl StdError for Error {
@@ -153,6 +153,13 @@ impl Error {
         self
     }
 
+    // Seconds sent in the `Retry-After` header
+    #[must_use]
+    pub const fn with_retry_after(mut self, seconds: u64) -> Self {
+        self.retry_after = Some(seconds);
+        self
+    }
+
     pub fn get_event(&self) -> &Option<ErrorEvent> {
         &self.event
     }
@@ -243,7 +250,12 @@ impl Responder<'_, 'static> for Error {
 
         let code = Status::from_code(self.error_code).unwrap_or(Status::BadRequest);
         let body = self.to_string();
-        Response::build().status(code).header(ContentType::JSON).sized_body(Some(body.len()), Cursor::new(body)).ok()
+        let mut response = Response::build();
+        response.status(code).header(ContentType::JSON).sized_body(Some(body.len()), Cursor::new(body));
+        if let Some(seconds) = self.retry_after {
+            response.raw_header("Retry-After", seconds.to_string());
+        }
+        response.ok()
     }
 }
 
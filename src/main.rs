@@ -56,8 +56,11 @@ mod business;
 mod db;
 mod http_client;
 mod mail;
+mod metrics;
 mod ratelimit;
+mod redis_client;
 mod sso;
+mod sso_claims;
 mod util;
 
 use crate::api::core::two_factor::duo_oidc::purge_duo_contexts;
@@ -91,6 +94,7 @@ async fn main() -> Result<(), Error> {
     let pool = create_db_pool().await;
     schedule_jobs(pool.clone());
     db::models::TwoFactor::migrate_u2f_to_webauthn(&mut pool.get().await.unwrap()).await.unwrap();
+    sso::check_default_org(&mut pool.get().await.unwrap()).await;
 
     let extra_debug = matches!(level, log::LevelFilter::Trace | log::LevelFilter::Debug);
     launch_rocket(pool, extra_debug).await // Blocks until program termination.
@@ -110,6 +114,7 @@ COMMAND:
     hash [--preset {bitwarden|owasp}]  Generate an Argon2id PHC ADMIN_TOKEN
     backup                             Create a backup of the SQLite database
                                        You can also send the USR1 signal to trigger a backup
+    sso-check                          Check the SSO configuration and provider connectivity
 
 PRESETS:                  m=         t=          p=
     bitwarden (default) 64MiB, 3 Iterations, 4 Threads
@@ -200,6 +205,13 @@ async fn parse_args() {
                     exit(1);
                 }
             }
+        } else if command == "sso-check" {
+            let success = sso::check().await;
+            exit(if success {
+                0
+            } else {
+                1
+            });
         }
         exit(0);
     }
@@ -578,6 +590,7 @@ async fn launch_rocket(pool: db::DbPool, extra_debug: bool) -> Result<(), Error>
         .mount([basepath, "/identity"].concat(), api::identity_routes())
         .mount([basepath, "/icons"].concat(), api::icons_routes())
         .mount([basepath, "/notifications"].concat(), api::notifications_routes())
+        .mount([basepath, "/scim/v2"].concat(), api::scim_routes())
         .register([basepath, "/"].concat(), api::web_catchers())
         .register([basepath, "/api"].concat(), api::core_catchers())
         .register([basepath, "/admin"].concat(), api::admin_catchers())
@@ -705,6 +718,14 @@ fn schedule_jobs(pool: db::DbPool) {
                 }));
             }
 
+            // Flag the SSO synced memberships not seen for `SSO_ORGANIZATIONS_STALE_DAYS` (default to daily at 00h40).
+            if !CONFIG.sso_organizations_stale_schedule().is_empty() && CONFIG.sso_organizations_stale_days().is_some()
+            {
+                sched.add(Job::new(CONFIG.sso_organizations_stale_schedule().parse().unwrap(), || {
+                    runtime.spawn(sso::flag_stale_memberships(pool.clone()));
+                }));
+            }
+
             // Periodically check for jobs to run. We probably won't need any
             // jobs that run more often than once a minute, so a default poll
             // interval of 30 seconds should be sufficient. Users who want to
@@ -0,0 +1,165 @@
+//
+// Minimal Prometheus metrics for the SSO flow.
+// Only atomics with a fixed set of labels: no allocation or lock on the request path.
+//
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    const fn new() -> Self {
+        Self(AtomicU64::new(0))
+    }
+
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// Upper bounds in seconds
+const BUCKETS: [f64; 10] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+
+pub struct Histogram {
+    buckets: [AtomicU64; BUCKETS.len()],
+    sum_micros: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    const fn new() -> Self {
+        Self {
+            buckets: [const { AtomicU64::new(0) }; BUCKETS.len()],
+            sum_micros: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        if let Some(index) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        }
+        self.sum_micros.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Time the future and record its duration
+    pub async fn time<F: std::future::Future>(&self, future: F) -> F::Output {
+        let start = Instant::now();
+        let output = future.await;
+        self.observe(start.elapsed());
+        output
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} histogram");
+        let mut cumulative = 0;
+        for (bound, bucket) in BUCKETS.iter().zip(self.buckets.iter()) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {cumulative}");
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {count}");
+        let _ = writeln!(out, "{name}_sum {}", self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0);
+        let _ = writeln!(out, "{name}_count {count}");
+    }
+}
+
+// Bounded set of failure reasons used as label
+#[derive(Clone, Copy)]
+pub enum ExchangeFailure {
+    InvalidState,
+    ProviderError,
+    TokenEndpoint,
+    IdToken,
+    Claims,
+}
+
+impl ExchangeFailure {
+    const ALL: [ExchangeFailure; 5] = [
+        ExchangeFailure::InvalidState,
+        ExchangeFailure::ProviderError,
+        ExchangeFailure::TokenEndpoint,
+        ExchangeFailure::IdToken,
+        ExchangeFailure::Claims,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            ExchangeFailure::InvalidState => "invalid_state",
+            ExchangeFailure::ProviderError => "provider_error",
+            ExchangeFailure::TokenEndpoint => "token_endpoint",
+            ExchangeFailure::IdToken => "id_token",
+            ExchangeFailure::Claims => "claims",
+        }
+    }
+}
+
+pub static SSO_AUTHORIZE: Counter = Counter::new();
+pub static SSO_EXCHANGE_SUCCESS: Counter = Counter::new();
+static SSO_EXCHANGE_FAILURES: [Counter; ExchangeFailure::ALL.len()] =
+    [const { Counter::new() }; ExchangeFailure::ALL.len()];
+pub static SSO_REDEEM: Counter = Counter::new();
+pub static SSO_DISCOVERY: Counter = Counter::new();
+pub static SSO_TOKEN_LATENCY: Histogram = Histogram::new();
+pub static SSO_USERINFO_LATENCY: Histogram = Histogram::new();
+
+pub fn sso_exchange_failure(reason: ExchangeFailure) {
+    SSO_EXCHANGE_FAILURES[reason as usize].inc();
+}
+
+fn render_counter(out: &mut String, name: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} counter\n{name} {value}");
+}
+
+fn render_gauge(out: &mut String, name: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} gauge\n{name} {value}");
+}
+
+// Prometheus text exposition format
+pub fn render_sso(pending_auth: u64, pending_nonces: u64) -> String {
+    let mut out = String::with_capacity(4096);
+
+    render_counter(&mut out, "vaultwarden_sso_authorize_total", "Authorization urls generated", SSO_AUTHORIZE.get());
+
+    let name = "vaultwarden_sso_code_exchange_total";
+    let _ = writeln!(out, "# HELP {name} Authorization code exchanges\n# TYPE {name} counter");
+    let _ = writeln!(out, "{name}{{result=\"success\"}} {}", SSO_EXCHANGE_SUCCESS.get());
+    for reason in ExchangeFailure::ALL {
+        let value = SSO_EXCHANGE_FAILURES[reason as usize].get();
+        let _ = writeln!(out, "{name}{{result=\"failure\",reason=\"{}\"}} {value}", reason.label());
+    }
+
+    render_counter(&mut out, "vaultwarden_sso_redeem_total", "Completed SSO logins", SSO_REDEEM.get());
+    render_counter(&mut out, "vaultwarden_sso_discovery_total", "Calls to the discovery endpoint", SSO_DISCOVERY.get());
+
+    SSO_TOKEN_LATENCY.render(&mut out, "vaultwarden_sso_token_request_seconds", "Token endpoint latency");
+    SSO_USERINFO_LATENCY.render(&mut out, "vaultwarden_sso_userinfo_request_seconds", "Userinfo endpoint latency");
+
+    render_gauge(&mut out, "vaultwarden_sso_pending_auth", "Authentications waiting to be redeemed", pending_auth);
+    render_gauge(&mut out, "vaultwarden_sso_nonces", "Non expired SsoNonce entries", pending_nonces);
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[rocket::async_test]
+    async fn test_histogram_time() {
+        let histogram = Histogram::new();
+        assert_eq!(histogram.time(async { 42 }).await, 42);
+
+        let mut out = String::new();
+        histogram.render(&mut out, "test_seconds", "Test");
+        assert!(out.contains("test_seconds_bucket{le=\"0.005\"} 1"));
+        assert!(out.contains("test_seconds_count 1"));
+    }
+}
@@ -1,7 +1,16 @@
 use once_cell::sync::Lazy;
-use std::{net::IpAddr, num::NonZeroU32, time::Duration};
+use std::{
+    net::IpAddr,
+    num::NonZeroU32,
+    time::{Duration, Instant},
+};
 
-use governor::{clock::DefaultClock, state::keyed::DashMapStateStore, Quota, RateLimiter};
+use dashmap::DashMap;
+use governor::{
+    clock::{Clock, DefaultClock},
+    state::keyed::DashMapStateStore,
+    NotUntil, Quota, RateLimiter,
+};
 
 use crate::{Error, CONFIG};
 
@@ -19,20 +28,76 @@ static LIMITER_ADMIN: Lazy<Limiter> = Lazy::new(|| {
     RateLimiter::keyed(Quota::with_period(seconds).expect("Non-zero admin ratelimit seconds").allow_burst(burst))
 });
 
+static LIMITER_SSO: Lazy<Limiter> = Lazy::new(|| {
+    let seconds = Duration::from_secs(CONFIG.sso_ratelimit_seconds());
+    let burst = NonZeroU32::new(CONFIG.sso_ratelimit_max_burst()).expect("Non-zero sso ratelimit burst");
+    RateLimiter::keyed(Quota::with_period(seconds).expect("Non-zero sso ratelimit seconds").allow_burst(burst))
+});
+
+static LIMITER_SSO_FAILURE: Lazy<Limiter> = Lazy::new(|| {
+    let seconds = Duration::from_secs(CONFIG.sso_failure_ratelimit_seconds());
+    let burst =
+        NonZeroU32::new(CONFIG.sso_failure_ratelimit_max_burst()).expect("Non-zero sso failure ratelimit burst");
+    RateLimiter::keyed(Quota::with_period(seconds).expect("Non-zero sso failure ratelimit seconds").allow_burst(burst))
+});
+
+// IPs which exceeded the failure limit, blocked until the next failure would be allowed.
+static SSO_BLOCKED: Lazy<DashMap<IpAddr, Instant>> = Lazy::new(DashMap::new);
+
+fn too_many_requests(msg: &str, retry_after: Duration) -> Error {
+    // Round up to avoid a retry a few milliseconds too early
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    error!("{msg}");
+    Error::new(msg, msg).with_code(429).with_retry_after(seconds)
+}
+
+fn wait_time(not_until: &NotUntil<<DefaultClock as Clock>::Instant>) -> Duration {
+    not_until.wait_time_from(DefaultClock::default().now())
+}
+
 pub fn check_limit_login(ip: &IpAddr) -> Result<(), Error> {
     match LIMITER_LOGIN.check_key(ip) {
         Ok(_) => Ok(()),
-        Err(_e) => {
-            err_code!("Too many login requests", 429);
-        }
+        Err(e) => Err(too_many_requests("Too many login requests", wait_time(&e))),
     }
 }
 
 pub fn check_limit_admin(ip: &IpAddr) -> Result<(), Error> {
     match LIMITER_ADMIN.check_key(ip) {
         Ok(_) => Ok(()),
-        Err(_e) => {
-            err_code!("Too many admin requests", 429);
+        Err(e) => Err(too_many_requests("Too many admin requests", wait_time(&e))),
+    }
+}
+
+pub fn check_limit_sso(ip: &IpAddr) -> Result<(), Error> {
+    check_sso_failures(ip)?;
+
+    match LIMITER_SSO.check_key(ip) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(too_many_requests("Too many SSO requests", wait_time(&e))),
+    }
+}
+
+// Refuse the request if the IP exceeded the failure limit, does not consume any quota.
+pub fn check_sso_failures(ip: &IpAddr) -> Result<(), Error> {
+    let now = Instant::now();
+    let blocked_until = SSO_BLOCKED.get(ip).map(|entry| *entry);
+
+    match blocked_until {
+        Some(until) if until > now => Err(too_many_requests("Too many failed SSO requests", until - now)),
+        Some(_) => {
+            SSO_BLOCKED.remove_if(ip, |_, until| *until <= now);
+            Ok(())
         }
+        None => Ok(()),
+    }
+}
+
+pub fn sso_failure(ip: &IpAddr) {
+    if let Err(e) = LIMITER_SSO_FAILURE.check_key(ip) {
+        let now = Instant::now();
+        warn!("Too many failed SSO requests from {ip}, blocking SSO requests for {:?}", wait_time(&e));
+        SSO_BLOCKED.retain(|_, until| *until > now);
+        SSO_BLOCKED.insert(*ip, now + wait_time(&e));
     }
 }
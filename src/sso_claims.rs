@@ -0,0 +1,638 @@
+// Reading of the claims returned by the provider (id_token, userinfo, claims sources and JOSE headers).
+// The input is untrusted: nothing here should panic, malformed values are handled as absent claims.
+use std::collections::HashSet;
+
+use serde_json::{Map, Value};
+
+// Number of items read from an array claim, guard against huge arrays
+pub const MAX_CLAIM_ITEMS: usize = 256;
+
+// Parse a JSON object, any other value or a malformed document is handled as no claims.
+// serde_json refuses documents nested deeper than 128 levels instead of overflowing the stack.
+pub fn parse_claims(data: &[u8]) -> Option<Value> {
+    match serde_json::from_slice::<Value>(data) {
+        Ok(claims @ Value::Object(_)) => Some(claims),
+        Ok(_) => None,
+        Err(err) => {
+            debug!("Invalid claims: {err}");
+            None
+        }
+    }
+}
+
+// Decode a base64url segment of a JWT (header or payload)
+pub fn decode_segment(segment: &str) -> Option<Value> {
+    let decoded = data_encoding::BASE64URL_NOPAD.decode(segment.trim_end_matches('=').as_bytes()).ok()?;
+    parse_claims(&decoded)
+}
+
+// Claims of a token only when it's a JWT (three segments), the signature is not checked
+pub fn jwt_payload(token: &str) -> Option<Value> {
+    match token.split('.').collect::<Vec<_>>()[..] {
+        [_, payload, _] => decode_segment(payload),
+        _ => None,
+    }
+}
+
+// Read a claim returned either as a string or an array of strings (ex: `amr`, `acr`), normalized to a set.
+// A string can hold several space separated values. `None` when absent, `Err` when it has another shape.
+pub fn string_set_claim(claims: &Value, name: &str) -> Option<Result<HashSet<String>, String>> {
+    let values = match claims.get(name)? {
+        Value::String(value) => value.split_whitespace().map(str::to_string).collect(),
+        Value::Array(items) if items.len() <= MAX_CLAIM_ITEMS => {
+            let mut values = HashSet::new();
+            for item in items {
+                match item.as_str() {
+                    Some(value) => values.insert(value.trim().to_string()),
+                    None => return Some(Err(format!("`{name}` contains a non string value {item}"))),
+                };
+            }
+            values
+        }
+        Value::Array(items) => return Some(Err(format!("`{name}` has too many values ({})", items.len()))),
+        value => return Some(Err(format!("`{name}` is not a string or an array of strings: {value}"))),
+    };
+    Some(Ok(values))
+}
+
+// Read a NumericDate claim (`exp`, `iat`, `nbf`), some providers return a float or a numeric string.
+// Fractional seconds are truncated. `None` when absent or null, `Err` when it has another shape.
+pub fn numeric_date_claim(claims: &Value, name: &str) -> Option<Result<i64, String>> {
+    let seconds = match claims.get(name)? {
+        Value::Null => return None,
+        Value::Number(number) => number.as_i64().or_else(|| number.as_f64().and_then(truncate_seconds)),
+        Value::String(text) => {
+            let text = text.trim();
+            text.parse::<i64>().ok().or_else(|| text.parse::<f64>().ok().and_then(truncate_seconds))
+        }
+        _ => None,
+    };
+    Some(seconds.ok_or_else(|| format!("`{name}` is not a valid timestamp: {}", claims[name])))
+}
+
+fn truncate_seconds(seconds: f64) -> Option<i64> {
+    // `i64::MAX as f64` rounds up, the bound is exclusive
+    (seconds.is_finite() && seconds >= i64::MIN as f64 && seconds < i64::MAX as f64).then_some(seconds.trunc() as i64)
+}
+
+#[derive(Debug, PartialEq)]
+enum ClaimPathSegment {
+    Key(String),
+    Index(usize),
+}
+
+// Parse a path such as `profile.emails[0]` or `["https://app/email"]`
+fn parse_claim_path(path: &str) -> Result<Vec<ClaimPathSegment>, String> {
+    let mut segments = Vec::new();
+    let mut chars = path.chars().peekable();
+    let mut key = String::new();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '.' => {
+                if key.is_empty() && segments.is_empty() {
+                    return Err(format!("empty segment in `{path}`"));
+                }
+                if !key.is_empty() {
+                    segments.push(ClaimPathSegment::Key(std::mem::take(&mut key)));
+                }
+                if matches!(chars.peek(), None | Some('.' | '[')) {
+                    return Err(format!("empty segment in `{path}`"));
+                }
+            }
+            '[' => {
+                if !key.is_empty() {
+                    segments.push(ClaimPathSegment::Key(std::mem::take(&mut key)));
+                }
+                let mut inner = String::new();
+                loop {
+                    match chars.next() {
+                        None => return Err(format!("unclosed `[` in `{path}`")),
+                        Some(']') if !inner.starts_with('"') || (inner.len() > 1 && inner.ends_with('"')) => break,
+                        Some(c) => inner.push(c),
+                    }
+                }
+                if inner.len() >= 2 && inner.starts_with('"') && inner.ends_with('"') {
+                    segments.push(ClaimPathSegment::Key(inner[1..inner.len() - 1].to_string()));
+                } else {
+                    match inner.parse::<usize>() {
+                        Ok(index) => segments.push(ClaimPathSegment::Index(index)),
+                        Err(_) => return Err(format!("invalid index `{inner}` in `{path}`")),
+                    }
+                }
+            }
+            c => key.push(c),
+        }
+    }
+
+    if !key.is_empty() {
+        segments.push(ClaimPathSegment::Key(key));
+    }
+    if segments.is_empty() {
+        return Err("empty path".to_string());
+    }
+
+    Ok(segments)
+}
+
+pub fn validate_claim_path(path: &str) -> Result<(), String> {
+    if path.starts_with('/') {
+        Ok(())
+    } else {
+        parse_claim_path(path).map(|_| ())
+    }
+}
+
+// A leading `/` is read as a JSON pointer like the other `*_TOKEN_PATH` options
+pub fn resolve_claim_path<'a>(claims: &'a Value, path: &str) -> Option<&'a Value> {
+    if path.starts_with('/') {
+        return claims.pointer(path);
+    }
+
+    parse_claim_path(path).ok()?.iter().try_fold(claims, |value, segment| match segment {
+        ClaimPathSegment::Key(key) => value.get(key),
+        ClaimPathSegment::Index(index) => value.get(index),
+    })
+}
+
+// Read the email at `SSO_EMAIL_CLAIM` from the id_token then from the userinfo response
+pub fn email_claim(path: &str, id_token_claims: &Value, user_info_claims: &Value) -> Option<String> {
+    [id_token_claims, user_info_claims].iter().find_map(|claims| resolve_claim_path(claims, path).and_then(email_value))
+}
+
+// Providers are inconsistent about the case of emails and the form of internationalized domains (Unicode or punycode),
+// they are matched against the accounts trimmed, lowercased and with the domain in punycode.
+pub fn normalize_email(email: &str) -> Result<String, String> {
+    let email = email.trim().to_lowercase();
+    match email.rsplit_once('@') {
+        Some((local, domain)) => Ok(format!("{local}@{}", crate::util::email_domain_to_ascii(domain)?)),
+        None => Ok(email),
+    }
+}
+
+// Unicode form of a normalized email with an internationalized domain, used to find the accounts saved in this form
+pub fn unicode_email(email: &str) -> Option<String> {
+    let (local, domain) = email.rsplit_once('@')?;
+    let unicode = url::quirks::domain_to_unicode(domain);
+    (!unicode.is_empty() && unicode != domain).then(|| format!("{local}@{unicode}"))
+}
+
+// Some providers send the email as an array, the first non-empty item which looks like an address is used
+pub fn email_value(value: &Value) -> Option<String> {
+    match value {
+        Value::String(email) => Some(email.trim().to_string()),
+        Value::Array(items) => items
+            .iter()
+            .take(MAX_CLAIM_ITEMS)
+            .filter_map(|item| item.as_str().map(str::trim))
+            .find(|email| looks_like_email(email))
+            .map(str::to_string),
+        _ => None,
+    }
+}
+
+fn looks_like_email(email: &str) -> bool {
+    match email.rsplit_once('@') {
+        Some((local, domain)) => !local.is_empty() && !domain.is_empty() && !email.contains(char::is_whitespace),
+        None => false,
+    }
+}
+
+// openidconnect only reads a string `email`, an array is replaced by its first address (removed if none)
+pub fn normalize_email_claim(claims: &mut Value) {
+    let Some(obj) = claims.as_object_mut() else {
+        return;
+    };
+
+    let email = match obj.get("email") {
+        Some(email @ Value::Array(_)) => email_value(email),
+        _ => return,
+    };
+
+    match email {
+        Some(email) => obj.insert("email".to_string(), Value::String(email)),
+        None => obj.remove("email"),
+    };
+}
+
+// Copy of an id_token with its `email` array replaced by the first address, `None` if the email is not an array.
+// The copy keeps the original signature, which has to be verified on the original token.
+pub fn normalize_jwt_email(token: &str) -> Option<String> {
+    let [header, payload, signature] = token.split('.').collect::<Vec<_>>()[..] else {
+        return None;
+    };
+
+    let mut claims = decode_segment(payload)?;
+    if !claims.get("email").is_some_and(Value::is_array) {
+        return None;
+    }
+    normalize_email_claim(&mut claims);
+
+    let payload = data_encoding::BASE64URL_NOPAD.encode(&serde_json::to_vec(&claims).ok()?);
+    Some(format!("{header}.{payload}.{signature}"))
+}
+
+// Merge the userinfo claims into the id_token ones (which take precedence) without the `redacted` claims.
+// When the result exceeds `max_size` bytes the largest claims are dropped, their names are returned.
+pub fn merge_claims(
+    id_token_claims: &Value,
+    user_info_claims: &Value,
+    redacted: &[String],
+    max_size: usize,
+) -> (Map<String, Value>, Vec<String>) {
+    let mut merged = Map::new();
+    for claims in [user_info_claims, id_token_claims] {
+        if let Some(claims) = claims.as_object() {
+            for (name, value) in claims {
+                if !redacted.contains(name) {
+                    merged.insert(name.clone(), value.clone());
+                }
+            }
+        }
+    }
+
+    // Serialized size of each `"name":value` member, the object adds the braces and a comma between members
+    let mut sizes: Vec<(String, usize)> = merged
+        .iter()
+        .map(|(name, value)| {
+            let size = Value::String(name.clone()).to_string().len() + 1 + value.to_string().len();
+            (name.clone(), size)
+        })
+        .collect();
+    sizes.sort_by_key(|(_, size)| *size);
+
+    let mut total = 2;
+    let mut dropped = vec![];
+    for (name, size) in sizes {
+        let added = size + usize::from(total > 2);
+        if total + added <= max_size {
+            total += added;
+        } else {
+            merged.remove(&name);
+            dropped.push(name);
+        }
+    }
+
+    (merged, dropped)
+}
+
+// Read the status at `SSO_ACCOUNT_STATUS_CLAIM` from the id_token then from the userinfo response.
+// Booleans and numbers are returned as text to be compared with the configured values.
+pub fn account_status_claim(path: &str, id_token_claims: &Value, user_info_claims: &Value) -> Option<String> {
+    [id_token_claims, user_info_claims].iter().find_map(|claims| match resolve_claim_path(claims, path)? {
+        Value::String(status) => Some(status.clone()),
+        Value::Bool(status) => Some(status.to_string()),
+        Value::Number(status) => Some(status.to_string()),
+        _ => None,
+    })
+}
+
+// Read the verified aliases at `SSO_EMAIL_ALIASES_CLAIM`, items are strings or objects with a `value`/`email`.
+// Returned lowercased, without duplicates nor the primary email. Only the first `MAX_CLAIM_ITEMS` items are read.
+pub fn email_aliases_claim(
+    path: &str,
+    primary: &str,
+    id_token_claims: &Value,
+    user_info_claims: &Value,
+) -> Vec<String> {
+    let items = [id_token_claims, user_info_claims].iter().find_map(|claims| match resolve_claim_path(claims, path) {
+        Some(Value::Array(items)) => Some(items.iter().take(MAX_CLAIM_ITEMS).collect::<Vec<_>>()),
+        Some(value @ Value::String(_)) => Some(vec![value]),
+        _ => None,
+    });
+
+    let mut aliases: Vec<String> = vec![];
+    for item in items.unwrap_or_default() {
+        let alias = match item {
+            Value::String(email) => Some(email.as_str()),
+            Value::Object(obj) if obj.get("verified").and_then(|v| v.as_bool()) != Some(false) => {
+                obj.get("value").or_else(|| obj.get("email")).and_then(|v| v.as_str())
+            }
+            _ => None,
+        };
+
+        if let Some(alias) = alias.and_then(|a| normalize_email(a).ok()).filter(|a| !a.is_empty()) {
+            if alias != primary && !aliases.contains(&alias) {
+                aliases.push(alias);
+            }
+        }
+    }
+
+    aliases
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    const KEYS: [&str; 8] = ["email", "emails", "profile", "value", "verified", "0", "https://app/email", ""];
+
+    fn random_value(rng: &mut StdRng, depth: usize) -> Value {
+        let kind = if depth == 0 {
+            rng.random_range(0..5)
+        } else {
+            rng.random_range(0..7)
+        };
+        match kind {
+            0 => Value::Null,
+            1 => Value::Bool(rng.random()),
+            2 => Value::from(rng.random::<i64>()),
+            3 => Value::from(rng.random::<f64>()),
+            4 => Value::String(KEYS[rng.random_range(0..KEYS.len())].to_string() + "@example.com"),
+            5 => Value::Array((0..rng.random_range(0..6)).map(|_| random_value(rng, depth - 1)).collect()),
+            _ => Value::Object(
+                (0..rng.random_range(0..6))
+                    .map(|_| (KEYS[rng.random_range(0..KEYS.len())].to_string(), random_value(rng, depth - 1)))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn random_path(rng: &mut StdRng) -> String {
+        const FRAGMENTS: [&str; 12] =
+            ["email", "emails", "profile", ".", "[", "]", "[0]", "[1]", "[\"", "\"]", "/", "/0"];
+        (0..rng.random_range(0..8)).map(|_| FRAGMENTS[rng.random_range(0..FRAGMENTS.len())]).collect()
+    }
+
+    #[test]
+    fn test_resolve_claim_path() {
+        let claims = serde_json::json!({
+            "email": "root@example.com",
+            "https://app/email": "namespaced@example.com",
+            "profile": { "emails": ["first@example.com", "second@example.com"] },
+        });
+
+        let resolve = |path| resolve_claim_path(&claims, path).and_then(|v| v.as_str());
+        assert_eq!(resolve("email"), Some("root@example.com"));
+        assert_eq!(resolve(r#"["https://app/email"]"#), Some("namespaced@example.com"));
+        assert_eq!(resolve("profile.emails[1]"), Some("second@example.com"));
+        assert_eq!(resolve(r#"["profile"]["emails"][0]"#), Some("first@example.com"));
+        assert_eq!(resolve("/profile/emails/0"), Some("first@example.com"));
+        assert_eq!(resolve("profile.missing"), None);
+
+        assert!(validate_claim_path("profile.emails[0]").is_ok());
+        assert!(validate_claim_path("").is_err());
+        assert!(validate_claim_path(".email").is_err());
+        assert!(validate_claim_path("profile..email").is_err());
+        assert!(validate_claim_path("profile.emails[first]").is_err());
+        assert!(validate_claim_path(r#"["https://app/email""#).is_err());
+    }
+
+    #[test]
+    fn test_email_aliases_claim() {
+        let id_token = serde_json::json!({
+            "emails": [
+                "Alias@Example.com",
+                "primary@example.com",
+                { "value": "object@example.com", "verified": true },
+                { "email": "fallback@example.com" },
+                { "value": "unverified@example.com", "verified": false },
+                "alias@example.com",
+                42,
+            ],
+        });
+        let user_info = serde_json::json!({ "other": "single@example.com" });
+
+        assert_eq!(
+            email_aliases_claim("emails", "primary@example.com", &id_token, &user_info),
+            vec!["alias@example.com", "object@example.com", "fallback@example.com"]
+        );
+        assert_eq!(
+            email_aliases_claim("other", "primary@example.com", &id_token, &user_info),
+            vec!["single@example.com"]
+        );
+        assert!(email_aliases_claim("missing", "primary@example.com", &id_token, &user_info).is_empty());
+    }
+
+    #[test]
+    fn test_normalize_email() {
+        assert_eq!(normalize_email(" John.Doe@Example.COM ").unwrap(), "john.doe@example.com");
+        assert_eq!(normalize_email("john.doe@example.com").unwrap(), "john.doe@example.com");
+
+        let claims = json!({ "email": "John.Doe@Example.COM" });
+        let email = email_claim("email", &claims, &json!({})).and_then(|email| normalize_email(&email).ok());
+        assert_eq!(email.as_deref(), Some("john.doe@example.com"));
+        assert_eq!(email_aliases_claim("email", "john.doe@example.com", &claims, &json!({})), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_normalize_idn_email() {
+        // Both forms give the punycode one
+        assert_eq!(normalize_email("user@bücher.example").unwrap(), "user@xn--bcher-kva.example");
+        assert_eq!(normalize_email("user@xn--bcher-kva.example").unwrap(), "user@xn--bcher-kva.example");
+        assert_eq!(normalize_email(" User.Name@BÜCHER.Example").unwrap(), "user.name@xn--bcher-kva.example");
+        assert_eq!(normalize_email("USER@XN--BCHER-KVA.EXAMPLE").unwrap(), "user@xn--bcher-kva.example");
+
+        assert_eq!(unicode_email("user@xn--bcher-kva.example").as_deref(), Some("user@bücher.example"));
+        assert_eq!(unicode_email("user@example.com"), None);
+
+        // Invalid sequences are refused instead of creating an account
+        for email in
+            ["user@xn--0.example", "user@exa mple.example", "user@ex%41mple.example", "user@127.0.0.1", "user@"]
+        {
+            let err = normalize_email(email).unwrap_err();
+            assert!(err.contains('`'), "{email}: {err}");
+        }
+
+        // Invalid aliases are skipped
+        let claims = json!({ "emails": ["alias@xn--0.example", "Alias@Bücher.example"] });
+        assert_eq!(
+            email_aliases_claim("emails", "user@xn--bcher-kva.example", &claims, &json!({})),
+            vec!["alias@xn--bcher-kva.example".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_merge_claims() {
+        let id_token = json!({ "sub": "1", "email": "user@example.com", "nonce": "n", "department": "IT" });
+        let user_info =
+            json!({ "sub": "2", "email": "other@example.com", "phone_number": "+1 555", "groups": ["a", "b"] });
+        let redacted = vec!["nonce".to_string(), "phone_number".to_string()];
+
+        let (merged, dropped) = merge_claims(&id_token, &user_info, &redacted, 1024);
+        assert!(dropped.is_empty());
+        assert_eq!(
+            Value::Object(merged),
+            json!({ "sub": "1", "email": "user@example.com", "department": "IT", "groups": ["a", "b"] })
+        );
+
+        // The largest claims are dropped first and the result fits
+        let user_info = json!({ "groups": (0..100).map(|i| format!("group-{i}")).collect::<Vec<_>>() });
+        let (merged, dropped) = merge_claims(&id_token, &user_info, &redacted, 100);
+        assert_eq!(dropped, vec!["groups".to_string()]);
+        assert!(Value::Object(merged.clone()).to_string().len() <= 100);
+        assert_eq!(merged.get("department"), Some(&json!("IT")));
+
+        let (merged, dropped) = merge_claims(&id_token, &Value::Null, &redacted, 0);
+        assert!(merged.is_empty());
+        assert_eq!(dropped.len(), 3);
+
+        let (merged, _) = merge_claims(&id_token, &Value::Null, &[], 1024);
+        assert_eq!(Value::Object(merged).to_string().len(), id_token.to_string().len());
+    }
+
+    #[test]
+    fn test_email_array_claim() {
+        let none = json!({});
+
+        let claims = json!({ "email": ["user@example.com", "other@example.com"] });
+        assert_eq!(email_claim("email", &claims, &none).as_deref(), Some("user@example.com"));
+
+        // Empty arrays fall back to the userinfo
+        let claims = json!({ "email": [] });
+        let user_info = json!({ "email": ["user@example.com"] });
+        assert_eq!(email_claim("email", &claims, &none), None);
+        assert_eq!(email_claim("email", &claims, &user_info).as_deref(), Some("user@example.com"));
+
+        // Garbage items are skipped
+        let claims = json!({ "emails": [null, "", "  ", 42, { "value": "a@b.c" }, "not an email", "@example.com", " user@example.com "] });
+        assert_eq!(email_claim("emails", &claims, &none).as_deref(), Some("user@example.com"));
+        assert_eq!(email_claim("emails", &json!({ "emails": [null, "nope", true] }), &none), None);
+
+        // Strings are kept as before, only trimmed
+        assert_eq!(
+            email_claim("email", &json!({ "email": " user@example.com\n" }), &none).as_deref(),
+            Some("user@example.com")
+        );
+
+        let mut user_info = json!({ "sub": "1", "email": ["", "user@example.com"] });
+        normalize_email_claim(&mut user_info);
+        assert_eq!(user_info, json!({ "sub": "1", "email": "user@example.com" }));
+
+        let mut user_info = json!({ "sub": "1", "email": [] });
+        normalize_email_claim(&mut user_info);
+        assert_eq!(user_info, json!({ "sub": "1" }));
+
+        let mut user_info = json!({ "sub": "1", "email": "user@example.com" });
+        normalize_email_claim(&mut user_info);
+        assert_eq!(user_info, json!({ "sub": "1", "email": "user@example.com" }));
+
+        // The id_token copy only differs by its payload
+        let encode = |value: &Value| data_encoding::BASE64URL_NOPAD.encode(value.to_string().as_bytes());
+        let id_token = format!("header.{}.signature", encode(&json!({ "sub": "1", "email": ["user@example.com"] })));
+        let normalized = normalize_jwt_email(&id_token).unwrap();
+        assert!(normalized.starts_with("header.") && normalized.ends_with(".signature"));
+        assert_eq!(jwt_payload(&normalized), Some(json!({ "sub": "1", "email": "user@example.com" })));
+
+        let id_token = format!("header.{}.signature", encode(&json!({ "sub": "1", "email": "user@example.com" })));
+        assert_eq!(normalize_jwt_email(&id_token), None);
+        assert_eq!(normalize_jwt_email("opaque"), None);
+    }
+
+    #[test]
+    fn test_numeric_date_claim() {
+        let claims = serde_json::json!({
+            "int": 1_700_000_000,
+            "float": 1_700_000_000.75,
+            "string": " 1700000000 ",
+            "null": null,
+            "text": "tomorrow",
+            "bool": true,
+            "huge": 1e300,
+        });
+
+        assert_eq!(numeric_date_claim(&claims, "int"), Some(Ok(1_700_000_000)));
+        assert_eq!(numeric_date_claim(&claims, "float"), Some(Ok(1_700_000_000)));
+        assert_eq!(numeric_date_claim(&claims, "string"), Some(Ok(1_700_000_000)));
+        assert_eq!(numeric_date_claim(&claims, "null"), None);
+        assert_eq!(numeric_date_claim(&claims, "missing"), None);
+        for name in ["text", "bool", "huge"] {
+            assert!(numeric_date_claim(&claims, name).unwrap().unwrap_err().contains(&format!("`{name}`")));
+        }
+    }
+
+    #[test]
+    fn test_jwt_payload() {
+        let encode = |value: &str| data_encoding::BASE64URL_NOPAD.encode(value.as_bytes());
+        let payload = encode(r#"{"realm_access":{"roles":["admin"]}}"#);
+        let jwt = format!("{}.{payload}.signature", encode(r#"{"alg":"RS256"}"#));
+
+        let claims = jwt_payload(&jwt).unwrap();
+        assert_eq!(claims.pointer("/realm_access/roles/0").and_then(Value::as_str), Some("admin"));
+        assert_eq!(jwt_payload("opaque-access-token"), None);
+        assert_eq!(jwt_payload(&format!("a.{payload}.b.c.d")), None);
+        assert_eq!(jwt_payload("a.not base64.b"), None);
+    }
+
+    #[test]
+    fn test_parse_claims() {
+        assert!(parse_claims(br#"{"sub": "1"}"#).is_some());
+        assert_eq!(parse_claims(br#"["sub"]"#), None);
+        assert_eq!(parse_claims(b"null"), None);
+        assert_eq!(parse_claims(br#"{"sub": "1""#), None);
+        assert_eq!(parse_claims(&[0xff, 0xfe]), None);
+        assert_eq!(
+            decode_segment("eyJhbGciOiJSUzI1NiJ9").and_then(|h| h["alg"].as_str().map(str::to_string)),
+            Some("RS256".to_string())
+        );
+        assert_eq!(decode_segment("not base64!"), None);
+
+        // Refused by the serde_json recursion limit
+        let nested = format!("{{\"a\":{}{}}}", "[".repeat(100_000), "]".repeat(100_000));
+        assert_eq!(parse_claims(nested.as_bytes()), None);
+    }
+
+    #[test]
+    fn test_string_set_claim() {
+        let set = |values: &[&str]| Some(Ok(values.iter().map(|v| (*v).to_string()).collect::<HashSet<_>>()));
+
+        // Spec shape, an array of strings
+        assert_eq!(string_set_claim(&serde_json::json!({ "amr": ["pwd", "mfa", "pwd"] }), "amr"), set(&["pwd", "mfa"]));
+        // Single string emitted by some providers
+        assert_eq!(string_set_claim(&serde_json::json!({ "amr": "mfa" }), "amr"), set(&["mfa"]));
+        assert_eq!(string_set_claim(&serde_json::json!({ "amr": "pwd otp" }), "amr"), set(&["pwd", "otp"]));
+        assert_eq!(string_set_claim(&serde_json::json!({ "acr": ["urn:loa:2"] }), "acr"), set(&["urn:loa:2"]));
+
+        assert_eq!(string_set_claim(&serde_json::json!({}), "amr"), None);
+        assert!(matches!(string_set_claim(&serde_json::json!({ "amr": ["pwd", 2] }), "amr"), Some(Err(_))));
+        assert!(matches!(string_set_claim(&serde_json::json!({ "amr": { "pwd": true } }), "amr"), Some(Err(_))));
+    }
+
+    #[test]
+    fn test_huge_array_claims() {
+        let emails: Vec<String> = (0..100_000).map(|i| format!("alias{i}@example.com")).collect();
+        let id_token = serde_json::json!({ "emails": emails });
+
+        let aliases = email_aliases_claim("emails", "primary@example.com", &id_token, &Value::Null);
+        assert_eq!(aliases.len(), MAX_CLAIM_ITEMS);
+        assert_eq!(aliases[0], "alias0@example.com");
+        assert!(matches!(string_set_claim(&id_token, "emails"), Some(Err(_))));
+    }
+
+    // Random claims, paths and corrupted documents: the parser must not panic
+    #[test]
+    fn test_fuzz_claims() {
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+
+        for _ in 0..2_000 {
+            let claims = random_value(&mut rng, 6);
+            let other = random_value(&mut rng, 3);
+            let path = random_path(&mut rng);
+
+            let _ = validate_claim_path(&path).is_ok();
+            let _ = resolve_claim_path(&claims, &path).is_some();
+            let _ = email_claim(&path, &claims, &other).is_some();
+            let _ = string_set_claim(&claims, &path).is_some();
+            assert!(email_aliases_claim(&path, "primary@example.com", &claims, &other).len() <= MAX_CLAIM_ITEMS);
+
+            let mut document = serde_json::to_vec(&claims).unwrap();
+            for _ in 0..rng.random_range(0..4) {
+                match rng.random_range(0..3) {
+                    0 if !document.is_empty() => {
+                        let at = rng.random_range(0..document.len());
+                        document[at] = rng.random();
+                    }
+                    1 if !document.is_empty() => document.truncate(rng.random_range(0..document.len())),
+                    _ => document.push(rng.random()),
+                }
+            }
+            if let Some(parsed) = parse_claims(&document) {
+                let _ = resolve_claim_path(&parsed, &path).is_some();
+            }
+
+            let segment = data_encoding::BASE64URL_NOPAD.encode(&document);
+            let _ = decode_segment(&segment).is_some();
+        }
+    }
+}
@@ -475,6 +475,21 @@ pub fn parse_date(date: &str) -> NaiveDateTime {
     DateTime::parse_from_rfc3339(date).unwrap().naive_utc()
 }
 
+/// Convert the domain of an email to its ASCII form (punycode for internationalized domains)
+///
+/// Providers send either the Unicode form (`bücher.example`) or the punycode one (`xn--bcher-kva.example`)
+pub fn email_domain_to_ascii(domain: &str) -> Result<String, String> {
+    // `Host::parse` would percent-decode the domain
+    if domain.contains('%') {
+        return Err(format!("`{domain}` is not a valid domain"));
+    }
+    match url::Host::parse(domain) {
+        Ok(url::Host::Domain(ascii)) => Ok(ascii),
+        Ok(_) => Err(format!("`{domain}` is an IP address, not a domain")),
+        Err(err) => Err(format!("`{domain}` is not a valid domain ({err})")),
+    }
+}
+
 /// Returns true or false if an email address is valid or not
 ///
 /// Some extra checks instead of only using email_address